@@ -0,0 +1,55 @@
+//! An example of a program that gates an operation behind a multisig decision.
+//!
+//! The protocol's admin is set to the signer PDA of a `Multisig` account. That
+//! address can only sign when the multisig program executes an approved
+//! transaction, so requiring it as a signer means the operation can only run
+//! once enough owners of the multisig have approved it.
+//!
+//! To use, derive the multisig signer with
+//! `findProgramAddress([multisig.publicKey.toBuffer()], multisigProgramId)`,
+//! pass it as the `admin` to `initialize`, and then propose a multisig
+//! transaction calling `set_value` with the signer as its `admin` account.
+
+use anchor_lang::prelude::*;
+
+#[program]
+pub mod example_protocol {
+    use super::*;
+
+    // Initializes the protocol with the address allowed to change it.
+    pub fn initialize(ctx: Context<Initialize>, admin: Pubkey) -> ProgramResult {
+        let protocol = &mut ctx.accounts.protocol;
+        protocol.admin = admin;
+        protocol.value = 0;
+        Ok(())
+    }
+
+    // Changes the protocol's value. Requires the admin to sign, which for a
+    // multisig signer only happens within an approved multisig transaction.
+    pub fn set_value(ctx: Context<SetValue>, value: u64) -> ProgramResult {
+        ctx.accounts.protocol.value = value;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct Initialize<'info> {
+    #[account(init)]
+    protocol: ProgramAccount<'info, Protocol>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct SetValue<'info> {
+    #[account(mut, has_one = admin)]
+    protocol: ProgramAccount<'info, Protocol>,
+    // The multisig signer, signed for by the multisig program.
+    #[account(signer)]
+    admin: AccountInfo<'info>,
+}
+
+#[account]
+pub struct Protocol {
+    admin: Pubkey,
+    value: u64,
+}
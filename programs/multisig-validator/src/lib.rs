@@ -0,0 +1,63 @@
+//! An on-chain check that an account is a real multisig.
+//!
+//! Protocols accepting a multisig (e.g. as a fee recipient or an admin) can
+//! CPI into `validate_multisig` to make sure the account they were given is a
+//! `Multisig` account of the multisig program, rather than a spoofed account
+//! with the same layout.
+//!
+//! Program IDs aren't known at compile time, so the caller passes the
+//! multisig program it trusts and is responsible for checking its address.
+
+use anchor_lang::prelude::*;
+
+#[program]
+pub mod multisig_validator {
+    use super::*;
+
+    // Succeeds if the given account is a well formed multisig owned by the
+    // given multisig program: it has the `Multisig` discriminator, its
+    // threshold can be reached by the summed weight of its owners, and its
+    // nonce produces its signer PDA.
+    pub fn validate_multisig(ctx: Context<ValidateMultisig>) -> Result<()> {
+        let info = &ctx.accounts.multisig;
+        if info.owner != ctx.accounts.multisig_program.key {
+            return Err(ErrorCode::NotAMultisig.into());
+        }
+        let data = info.try_borrow_data()?;
+        // Checks the discriminator.
+        let multisig = multisig::Multisig::try_deserialize(&mut &data[..])
+            .map_err(|_| ErrorCode::NotAMultisig)?;
+
+        let total_weight = multisig
+            .total_weight()
+            .map_err(|_| ErrorCode::InvalidThreshold)?;
+        if multisig.threshold() > total_weight {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+        Pubkey::create_program_address(
+            &[info.key.as_ref(), &[multisig.nonce()]],
+            ctx.accounts.multisig_program.key,
+        )
+        .map_err(|_| ErrorCode::InvalidNonce)?;
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+pub struct ValidateMultisig<'info> {
+    // The account to check. Checked in the handler.
+    multisig: AccountInfo<'info>,
+    // The multisig program the caller trusts.
+    #[account(executable)]
+    multisig_program: AccountInfo<'info>,
+}
+
+#[error]
+pub enum ErrorCode {
+    #[msg("The account is not a multisig of the given program.")]
+    NotAMultisig,
+    #[msg("The threshold is larger than the owners' total weight.")]
+    InvalidThreshold,
+    #[msg("The nonce does not produce a valid multisig signer.")]
+    InvalidNonce,
+}
@@ -17,227 +17,6328 @@
 //! the `execute_transaction`, once enough (i.e. `threhsold`) of the owners have
 //! signed.
 
+// The handlers generated by `#[program]` take every instruction argument.
+#![allow(clippy::too_many_arguments)]
+
 use anchor_lang::prelude::*;
 use anchor_lang::solana_program;
+use anchor_lang::solana_program::bpf_loader_upgradeable::{self, UpgradeableLoaderState};
+use anchor_lang::solana_program::hash;
 use anchor_lang::solana_program::instruction::Instruction;
+use anchor_lang::solana_program::program_option::COption;
+use anchor_lang::solana_program::program_pack::Pack;
+use anchor_lang::solana_program::system_instruction;
+use anchor_lang::solana_program::system_program;
+use anchor_lang::solana_program::sysvar;
+use anchor_lang::InstructionData;
 use std::convert::Into;
+use token::TokenAccount;
 
 #[program]
 pub mod multisig {
     use super::*;
 
-    // Initializes a new multisig account with a set of owners and a threshold.
+    // Initializes a new multisig account with a set of owners and a threshold,
+    // and optionally a cold recovery key, which can't be changed afterwards.
     pub fn create_multisig(
         ctx: Context<CreateMultisig>,
         owners: Vec<Pubkey>,
         threshold: u64,
         nonce: u8,
+        cold_recovery_key: Option<Pubkey>,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        check_new_multisig(&owners, threshold)?;
+        check_nonce(
+            ctx.accounts.multisig.to_account_info().key,
+            nonce,
+            ctx.program_id,
+        )?;
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.owners = owners;
+        multisig.threshold = threshold;
+        multisig.nonce = nonce;
+        multisig.cold_recovery_key = cold_recovery_key;
+        multisig.last_executed_at = Clock::get()?.unix_timestamp;
+
+        emit!(MultisigCreated {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+        });
+        Ok(())
+    }
+
+    // Initializes a new multisig mirroring the owners and threshold of a Squads
+    // v3 multisig. The caller states the owners and threshold it expects, and
+    // the migration fails unless they match the Squads account.
+    pub fn migrate_from_squads_v3(
+        ctx: Context<MigrateFromSquads>,
+        squads_multisig: Pubkey,
+        owners: Vec<Pubkey>,
+        threshold: u64,
+        nonce: u8,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        if &squads_multisig != ctx.accounts.squads_multisig.key {
+            return Err(ErrorCode::InvalidSquadsMultisig.into());
+        }
+        let ms = squads_v3::Ms::decode(&ctx.accounts.squads_multisig)?;
+        let mut expected = owners.clone();
+        let mut actual = ms.keys;
+        expected.sort();
+        actual.sort();
+        if expected != actual || threshold != u64::from(ms.threshold) {
+            return Err(ErrorCode::SquadsMultisigMismatch.into());
+        }
+        check_new_multisig(&owners, threshold)?;
+        check_nonce(
+            ctx.accounts.multisig.to_account_info().key,
+            nonce,
+            ctx.program_id,
+        )?;
+
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.owners = owners;
+        multisig.threshold = threshold;
+        multisig.nonce = nonce;
+        multisig.last_executed_at = Clock::get()?.unix_timestamp;
+
+        emit!(MultisigCreated {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+        });
+        emit!(MigratedFromSquadsV3 {
+            multisig: *multisig.to_account_info().key,
+            squads_multisig,
+        });
+        Ok(())
+    }
+
+    // Initializes a new multisig account and transfers the upgrade authority of
+    // the given program from the caller to the new multisig signer.
+    pub fn create_multisig_and_assume_upgrade_authority(
+        ctx: Context<CreateAndAssume>,
+        program: Pubkey,
+        owners: Vec<Pubkey>,
+        threshold: u64,
+        nonce: u8,
+        cold_recovery_key: Option<Pubkey>,
     ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let multisig_key = *ctx.accounts.multisig.to_account_info().key;
+        check_nonce(&multisig_key, nonce, ctx.program_id)?;
+        let multisig_signer =
+            Pubkey::create_program_address(&[multisig_key.as_ref(), &[nonce]], ctx.program_id)
+                .map_err(|_| ErrorCode::InvalidMultisigSigner)?;
+        if &multisig_signer != ctx.accounts.multisig_signer.key {
+            return Err(ErrorCode::InvalidMultisigSigner.into());
+        }
+        check_new_multisig(&owners, threshold)?;
+
         let multisig = &mut ctx.accounts.multisig;
         multisig.owners = owners;
         multisig.threshold = threshold;
         multisig.nonce = nonce;
+        multisig.cold_recovery_key = cold_recovery_key;
+        multisig.last_executed_at = Clock::get()?.unix_timestamp;
+
+        emit!(MultisigCreated {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+        });
+
+        let ix = bpf_loader_upgradeable::set_upgrade_authority(
+            &program,
+            ctx.accounts.upgrade_authority.key,
+            Some(&multisig_signer),
+        );
+        solana_program::program::invoke(
+            &ix,
+            &[
+                ctx.accounts.program_data.clone(),
+                ctx.accounts.upgrade_authority.clone(),
+                ctx.accounts.multisig_signer.clone(),
+                ctx.accounts.loader.clone(),
+            ],
+        )?;
         Ok(())
     }
 
+    // Executes the given instructions signed by the multisig, without any
+    // owner approvals. Last resort for a multisig that lost its quorum, only
+    // available to the cold recovery key once `COLD_RECOVERY_GRACE_PERIOD` has
+    // passed since the multisig last executed anything.
+    pub fn cold_recovery_execute<'info>(
+        ctx: Context<'_, '_, '_, 'info, ColdRecovery<'info>>,
+        pid: Vec<Pubkey>,
+        accs: Vec<Vec<TransactionAccount>>,
+        data: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let multisig = &ctx.accounts.multisig;
+        multisig.check_not_frozen()?;
+        if multisig.cold_recovery_key != Some(*ctx.accounts.recovery_key.key) {
+            return Err(ErrorCode::InvalidColdRecoveryKey.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now
+            .checked_sub(multisig.last_executed_at)
+            .ok_or(ErrorCode::Overflow)?;
+        if elapsed < COLD_RECOVERY_GRACE_PERIOD {
+            return Err(ErrorCode::ColdRecoveryTooEarly.into());
+        }
+
+        let ixs = instructions(pid, accs, data)?;
+        let accounts = TransactionAccountMap::new(ctx.remaining_accounts);
+        execute(
+            &ctx.accounts.multisig,
+            ctx.program_id,
+            0,
+            None,
+            &accounts,
+            ixs,
+            0,
+        )?;
+        record_execution(&mut ctx.accounts.multisig)
+    }
+
     // Creates a new transaction account, automatically signed by the creator,
-    // which must be one of the owners of the multisig.
-    pub fn create_transaction(
-        ctx: Context<CreateTransaction>,
+    // which must be one of the owners of the multisig. If `expires_at` is
+    // given, the transaction can't be approved or executed from then on. The
+    // instructions are signed by the vault at `vault_index` (see `pda::vault`),
+    // and by the first `ephemeral_signers` of the transaction's ephemeral
+    // signers (see `pda::ephemeral_signer`), e.g. for accounts to create.
+    pub fn create_transaction<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        pid: Vec<Pubkey>,
+        accs: Vec<Vec<TransactionAccount>>,
+        data: Vec<Vec<u8>>,
+        sort_instructions_by_program: bool,
+        expires_at: Option<i64>,
+        vault_index: u8,
+        ephemeral_signers: u8,
+    ) -> Result<()> {
+        let ixs = instructions(pid, accs, data)?;
+        ctx.accounts.transaction.sort_instructions_by_program = sort_instructions_by_program;
+        ctx.accounts.transaction.expires_at = expires_at;
+        ctx.accounts.transaction.vault_index = vault_index;
+        ctx.accounts.transaction.ephemeral_signers = ephemeral_signers;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            ixs,
+        )
+    }
+
+    // Creates a new transaction like `create_transaction`, at the address
+    // derived from the multisig and the transaction's index (see
+    // `pda::transaction`), paid for by the proposer. Indices count up from zero
+    // over all transactions of the multisig.
+    pub fn create_transaction_pda<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransactionPda<'info>>,
+        space: u64,
         pid: Vec<Pubkey>,
         accs: Vec<Vec<TransactionAccount>>,
         data: Vec<Vec<u8>>,
+        sort_instructions_by_program: bool,
+        expires_at: Option<i64>,
+        vault_index: u8,
+        ephemeral_signers: u8,
     ) -> Result<()> {
-        if pid.len() != accs.len() || pid.len() != data.len() {
-            return Err(ErrorCode::ParamLength.into());
+        let multisig_key = *ctx.accounts.multisig.to_account_info().key;
+        let index = ctx.accounts.multisig.transaction_count;
+        let (address, bump) = pda::transaction(&multisig_key, index, ctx.program_id);
+        if &address != ctx.accounts.transaction.key {
+            return Err(ErrorCode::InvalidTransaction.into());
         }
-        let owner_index = ctx
+        let ix = system_instruction::create_account(
+            ctx.accounts.proposer.key,
+            &address,
+            Rent::get()?.minimum_balance(space as usize),
+            space,
+            ctx.program_id,
+        );
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.proposer.clone(),
+                ctx.accounts.transaction.clone(),
+                ctx.accounts.system_program.clone(),
+            ],
+            &[&[
+                pda::TRANSACTION_SEED,
+                multisig_key.as_ref(),
+                &index.to_le_bytes(),
+                &[bump],
+            ]],
+        )?;
+
+        let mut tx: ProgramAccount<Transaction> =
+            ProgramAccount::try_from_init(&ctx.accounts.transaction)?;
+        tx.sort_instructions_by_program = sort_instructions_by_program;
+        tx.expires_at = expires_at;
+        tx.vault_index = vault_index;
+        tx.ephemeral_signers = ephemeral_signers;
+        let ixs = instructions(pid, accs, data)?;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut tx,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            ixs,
+        )?;
+        tx.exit(ctx.program_id)?;
+        Ok(())
+    }
+
+    // Proposes changes to the multisig's own config, applied in order on
+    // execution, instead of instructions calling back into the program. Signers
+    // can then review e.g. "add owner X, threshold 3" rather than raw bytes.
+    // Each action is held to the policies of the setter instruction it stands
+    // for, see `config_instructions`.
+    pub fn propose_config_change<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        actions: Vec<ConfigAction>,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        if actions.is_empty() {
+            return Err(ErrorCode::InvalidConfigChange.into());
+        }
+        ctx.accounts.transaction.expires_at = expires_at;
+        ctx.accounts.transaction.config_actions = actions;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            Vec::new(),
+        )
+    }
+
+    // Proposes transferring lamports from the multisig signer to `recipient`,
+    // recorded in readable fields for signers to review instead of raw
+    // instruction bytes.
+    pub fn propose_sol_transfer<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        recipient: Pubkey,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let multisig_signer = ctx
             .accounts
             .multisig
-            .owners
-            .iter()
-            .position(|a| a == ctx.accounts.proposer.key)
-            .ok_or(ErrorCode::InvalidOwner)?;
+            .signer(ctx.accounts.multisig.to_account_info().key, ctx.program_id)?;
+        let ix = system_instruction::transfer(&multisig_signer, &recipient, amount);
+        ctx.accounts.transaction.expires_at = expires_at;
+        ctx.accounts.transaction.sol_transfer = Some(SolTransfer { recipient, amount });
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-        let mut signers = Vec::new();
-        signers.resize(ctx.accounts.multisig.owners.len(), false);
-        signers[owner_index] = true;
+    // Creates a draft transaction without instructions, for proposals too
+    // large to fit in a single Solana transaction. The proposer appends the
+    // instructions with `add_instruction`, and `finalize_transaction` then
+    // proposes them as `create_transaction` would. Drafts can't be approved.
+    pub fn create_draft_transaction(
+        ctx: Context<CreateTransaction>,
+        sort_instructions_by_program: bool,
+        expires_at: Option<i64>,
+        vault_index: u8,
+        ephemeral_signers: u8,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let multisig = &ctx.accounts.multisig;
+        multisig.check_not_frozen()?;
+        let proposer = ctx.accounts.proposer.key;
+        multisig.check_permission(proposer, PERMISSION_PROPOSE)?;
 
         let tx = &mut ctx.accounts.transaction;
-        tx.program_id = pid;
-        tx.accounts = accs;
-        tx.data = data;
-        tx.signers = signers;
-        tx.multisig = *ctx.accounts.multisig.to_account_info().key;
-        tx.did_execute = false;
+        tx.multisig = *multisig.to_account_info().key;
+        tx.proposer = *proposer;
+        tx.status = TransactionStatus::Draft;
+        tx.sort_instructions_by_program = sort_instructions_by_program;
+        tx.expires_at = expires_at;
+        tx.vault_index = vault_index;
+        tx.ephemeral_signers = ephemeral_signers;
+        Ok(())
+    }
+
+    // Creates a draft transaction holding only the hash of its instructions,
+    // for proposals whose payload shouldn't be public until revealed with
+    // `reveal_transaction`. See `instructions_hash` for the hash.
+    pub fn create_hashed_transaction(
+        ctx: Context<CreateTransaction>,
+        instructions_hash: [u8; 32],
+        sort_instructions_by_program: bool,
+        expires_at: Option<i64>,
+        vault_index: u8,
+        ephemeral_signers: u8,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let multisig = &ctx.accounts.multisig;
+        multisig.check_not_frozen()?;
+        let proposer = ctx.accounts.proposer.key;
+        multisig.check_permission(proposer, PERMISSION_PROPOSE)?;
 
+        let tx = &mut ctx.accounts.transaction;
+        tx.multisig = *multisig.to_account_info().key;
+        tx.proposer = *proposer;
+        tx.status = TransactionStatus::Draft;
+        tx.sort_instructions_by_program = sort_instructions_by_program;
+        tx.expires_at = expires_at;
+        tx.vault_index = vault_index;
+        tx.ephemeral_signers = ephemeral_signers;
+        tx.instructions_hash = Some(instructions_hash);
         Ok(())
     }
 
-    // Approve and Executes the given transaction if threshold owners have signed it.
-    pub fn approve(ctx: Context<Approve>) -> Result<()> {
-        let owner_index = ctx
+    // Reveals the instructions of a hashed transaction and proposes them as
+    // `create_transaction` would, failing unless they match the hash.
+    pub fn reveal_transaction<'info>(
+        ctx: Context<'_, '_, '_, 'info, EditDraft<'info>>,
+        pid: Vec<Pubkey>,
+        accs: Vec<Vec<TransactionAccount>>,
+        data: Vec<Vec<u8>>,
+    ) -> Result<()> {
+        let expected = ctx
             .accounts
+            .transaction
+            .instructions_hash
+            .ok_or(ErrorCode::NotHashedTransaction)?;
+        if instructions_hash(&pid, &accs, &data)? != expected {
+            return Err(ErrorCode::InstructionsHashMismatch.into());
+        }
+        let ixs = instructions(pid, accs, data)?;
+        ctx.accounts.transaction.instructions_hash = None;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            ixs,
+        )
+    }
+
+    // Appends an instruction to a draft transaction.
+    pub fn add_instruction(
+        ctx: Context<EditDraft>,
+        pid: Pubkey,
+        accs: Vec<TransactionAccount>,
+        data: Vec<u8>,
+    ) -> Result<()> {
+        let tx = &mut ctx.accounts.transaction;
+        if tx.instructions_hash.is_some() {
+            return Err(ErrorCode::InstructionsHashMismatch.into());
+        }
+        tx.program_id.push(pid);
+        tx.accounts.push(accs);
+        tx.data.push(data);
+        Ok(())
+    }
+
+    // Locks the instructions of a draft transaction and proposes them,
+    // automatically approved by the proposer.
+    pub fn finalize_transaction<'info>(
+        ctx: Context<'_, '_, '_, 'info, EditDraft<'info>>,
+    ) -> Result<()> {
+        if ctx.accounts.transaction.instructions_hash.is_some() {
+            return Err(ErrorCode::InstructionsHashMismatch.into());
+        }
+        let ixs: Vec<Instruction> = (&*ctx.accounts.transaction).into();
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            ixs,
+        )
+    }
+
+    // Proposes up to `MAX_BATCH_SIZE` independent transactions at once. The new,
+    // zero initialized transaction accounts are given, in order, as the first
+    // remaining accounts, followed by any accounts needed for the proposal fee.
+    // Each transaction's description becomes its memo, and its eta the
+    // earliest time it may execute. The created transactions are listed by a
+    // `TransactionsBatchCreated` event, as anchor 0.6 can't return data.
+    pub fn batch_create_transactions<'info>(
+        ctx: Context<'_, '_, '_, 'info, BatchCreate<'info>>,
+        batch: Vec<BatchTransactionParams>,
+    ) -> Result<()> {
+        if batch.len() > MAX_BATCH_SIZE as usize {
+            msg!(
+                "Batch of {} exceeds the maximum of {}",
+                batch.len(),
+                MAX_BATCH_SIZE
+            );
+            return Err(ErrorCode::BatchTooLarge.into());
+        }
+        if ctx.remaining_accounts.len() < batch.len() {
+            return Err(ErrorCode::MissingAccount.into());
+        }
+        let (tx_accounts, remaining_accounts) = ctx.remaining_accounts.split_at(batch.len());
+        let rent = Rent::get()?;
+        let mut transactions = Vec::with_capacity(batch.len());
+        for (params, info) in batch.into_iter().zip(tx_accounts) {
+            if params.description.len() > MAX_MEMO_LEN {
+                return Err(ErrorCode::MemoTooLong.into());
+            }
+            if info.owner != ctx.program_id
+                || !info.is_writable
+                || !rent.is_exempt(info.lamports(), info.data_len())
+            {
+                return Err(ErrorCode::InvalidBatchTransaction.into());
+            }
+            let mut tx: ProgramAccount<Transaction> = ProgramAccount::try_from_init(info)?;
+            tx.sort_instructions_by_program = params.sort_instructions_by_program;
+            tx.expires_at = params.expires_at;
+            tx.vault_index = params.vault_index;
+            tx.ephemeral_signers = params.ephemeral_signers;
+            if !params.description.is_empty() {
+                tx.memo = Some(params.description);
+            }
+            tx.not_before = params.eta;
+            let ixs = instructions(params.pid, params.accs, params.data)?;
+            propose(
+                ctx.program_id,
+                &mut ctx.accounts.multisig,
+                &mut tx,
+                &ctx.accounts.proposer,
+                remaining_accounts,
+                ixs,
+            )?;
+            tx.exit(ctx.program_id)?;
+            transactions.push(*info.key);
+        }
+
+        emit!(TransactionsBatchCreated {
+            multisig: *ctx.accounts.multisig.to_account_info().key,
+            transactions,
+        });
+        Ok(())
+    }
+
+    // Creates an instruction buffer for a proposal whose instructions are too
+    // large to deserialize cheaply on every approval. The proposer writes the
+    // instructions with `write_instruction_buffer` and proposes them with
+    // `create_transaction_from_buffer`.
+    pub fn create_instruction_buffer(ctx: Context<CreateInstructionBuffer>) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let proposer = ctx.accounts.proposer.key;
+        ctx.accounts.multisig.check_not_frozen()?;
+        ctx.accounts
             .multisig
-            .owners
-            .iter()
-            .position(|a| a == ctx.accounts.owner.key)
-            .ok_or(ErrorCode::InvalidOwner)?;
+            .check_permission(proposer, PERMISSION_PROPOSE)?;
+        let mut buffer = ctx.accounts.buffer.load_init()?;
+        buffer.multisig = *ctx.accounts.multisig.to_account_info().key;
+        buffer.proposer = *proposer;
+        Ok(())
+    }
+
+    // Writes the given bytes of the borsh encoded `Vec<BufferedInstruction>` at
+    // the given offset of an instruction buffer not proposed yet.
+    pub fn write_instruction_buffer(
+        ctx: Context<WriteInstructionBuffer>,
+        offset: u32,
+        bytes: Vec<u8>,
+    ) -> Result<()> {
+        let mut buffer = ctx.accounts.buffer.load_mut()?;
+        if &buffer.proposer != ctx.accounts.proposer.key || buffer.locked != 0 {
+            return Err(ErrorCode::InvalidInstructionBuffer.into());
+        }
+        let start = offset as usize;
+        let end = start.checked_add(bytes.len()).ok_or(ErrorCode::Overflow)?;
+        if end > buffer.data.len() {
+            return Err(ErrorCode::InstructionBufferFull.into());
+        }
+        buffer.data[start..end].copy_from_slice(&bytes);
+        if end as u64 > buffer.len {
+            buffer.len = end as u64;
+        }
+        Ok(())
+    }
 
-        ctx.accounts.transaction.signers[owner_index] = true;
+    // Proposes the instructions of the given instruction buffer, which can't
+    // be written anymore. The transaction only references the buffer, so it
+    // stays small however large the instructions are, and the buffer must be
+    // among the remaining accounts on execution.
+    pub fn create_transaction_from_buffer<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransactionFromBuffer<'info>>,
+        sort_instructions_by_program: bool,
+        expires_at: Option<i64>,
+        vault_index: u8,
+        ephemeral_signers: u8,
+    ) -> Result<()> {
+        let ixs = {
+            let mut buffer = ctx.accounts.buffer.load_mut()?;
+            if buffer.multisig != *ctx.accounts.multisig.to_account_info().key
+                || &buffer.proposer != ctx.accounts.proposer.key
+                || buffer.locked != 0
+            {
+                return Err(ErrorCode::InvalidInstructionBuffer.into());
+            }
+            buffer.locked = 1;
+            buffer.instructions()?
+        };
+        let tx = &mut ctx.accounts.transaction;
+        tx.sort_instructions_by_program = sort_instructions_by_program;
+        tx.expires_at = expires_at;
+        tx.vault_index = vault_index;
+        tx.ephemeral_signers = ephemeral_signers;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            ixs,
+        )?;
+        // Validated, but read from the buffer on execution.
+        let tx = &mut ctx.accounts.transaction;
+        tx.program_id = Vec::new();
+        tx.accounts = Vec::new();
+        tx.data = Vec::new();
+        tx.instruction_buffer = Some(*ctx.accounts.buffer.to_account_info().key);
+        Ok(())
+    }
+
+    // Creates a new transaction from a JSON array of instructions, each given as
+    // `{ "programId": "...", "accounts": [...], "data": "<base64>" }`. Only
+    // available with the `json-instructions` feature, as a convenience for
+    // testing and prototyping.
+    pub fn create_transaction_from_json<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        json_instructions: String,
+    ) -> Result<()> {
+        let ixs = json::parse_instructions(&json_instructions)?;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            ixs,
+        )
+    }
 
-        // Do we have enough signers.
-        let sig_count = ctx
+    // Proposes closing an empty token account owned by the multisig signer,
+    // returning its rent to the destination.
+    pub fn propose_close_token_account<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeCloseAta<'info>>,
+        token_account: Pubkey,
+        destination: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let (signer, account) = ctx
             .accounts
-            .transaction
-            .signers
-            .iter()
-            .filter(|&did_sign| *did_sign)
-            .count() as u64;
-        if sig_count < ctx.accounts.multisig.threshold {
-            return Ok(());
+            .signer_token_account(ctx.program_id, &token_account)?;
+        if account.amount != 0 {
+            return Err(ErrorCode::NonZeroTokenBalance.into());
         }
+        let ix = spl_token::instruction::close_account(
+            &spl_token::ID,
+            &token_account,
+            &destination,
+            &signer,
+            &[],
+        )?;
+        ctx.accounts.transaction.expires_at = expires_at;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-        // Has this been executed already?
-        if ctx.accounts.transaction.did_execute {
-            return Err(ErrorCode::AlreadyExecuted.into());
+    // Proposes burning the remaining balance of a token account owned by the
+    // multisig signer and then closing it.
+    pub fn propose_burn_and_close<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeCloseAta<'info>>,
+        token_account: Pubkey,
+        destination: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let (signer, account) = ctx
+            .accounts
+            .signer_token_account(ctx.program_id, &token_account)?;
+        let burn_ix = spl_token::instruction::burn(
+            &spl_token::ID,
+            &token_account,
+            &account.mint,
+            &signer,
+            &[],
+            account.amount,
+        )?;
+        let close_ix = spl_token::instruction::close_account(
+            &spl_token::ID,
+            &token_account,
+            &destination,
+            &signer,
+            &[],
+        )?;
+        ctx.accounts.transaction.expires_at = expires_at;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![burn_ix, close_ix],
+        )
+    }
+
+    // Proposes transferring tokens of the given mint from the multisig
+    // signer's associated token account to a token account of the same mint,
+    // recorded in readable fields for signers to review instead of raw
+    // instruction bytes.
+    pub fn propose_token_transfer<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeTokenTransfer<'info>>,
+        mint: Pubkey,
+        destination: Pubkey,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let (signer, account) =
+            ctx.accounts
+                .signer_token_accounts(ctx.program_id, &mint, &destination)?;
+        let token_account = *ctx.accounts.token_account.key;
+        let ix = spl_token::instruction::transfer(
+            &spl_token::ID,
+            &token_account,
+            &destination,
+            &signer,
+            &[],
+            amount,
+        )?;
+        ctx.accounts.transaction.expires_at = expires_at;
+        ctx.accounts.transaction.token_transfer = Some(TokenTransfer {
+            source: token_account,
+            mint: account.mint,
+            destination,
+            amount,
+        });
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
+
+    // Proposes minting tokens of a mint whose mint authority is the multisig
+    // signer to the destination token account.
+    pub fn propose_mint_to<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeMintAuthority<'info>>,
+        destination: Pubkey,
+        amount: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let (signer, mint) = ctx.accounts.signer_mint(ctx.program_id)?;
+        if mint.mint_authority != COption::Some(signer) {
+            return Err(ErrorCode::InvalidMintAuthority.into());
         }
+        let ix = spl_token::instruction::mint_to(
+            &spl_token::ID,
+            ctx.accounts.mint.key,
+            &destination,
+            &signer,
+            &[],
+            amount,
+        )?;
+        ctx.accounts.transaction.expires_at = expires_at;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-        // Execute the transaction signed by the multisig.
-        let mut ixs: Vec<Instruction> = (&*ctx.accounts.transaction).into();
-        for ix in ixs.iter_mut() {
-            ix.accounts = ix
-                .accounts
-                .iter()
-                .map(|acc| {
-                    let mut acc = acc.clone();
-                    if &acc.pubkey == ctx.accounts.multisig_signer.key {
-                        acc.is_signer = true;
-                    }
-                    acc
-                })
-                .collect();
+    // Proposes freezing a token account of a mint whose freeze authority is
+    // the multisig signer.
+    pub fn propose_freeze_token_account<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeMintAuthority<'info>>,
+        token_account: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let (signer, mint) = ctx.accounts.signer_mint(ctx.program_id)?;
+        if mint.freeze_authority != COption::Some(signer) {
+            return Err(ErrorCode::InvalidMintAuthority.into());
         }
+        let ix = spl_token::instruction::freeze_account(
+            &spl_token::ID,
+            &token_account,
+            ctx.accounts.mint.key,
+            &signer,
+            &[],
+        )?;
+        ctx.accounts.transaction.expires_at = expires_at;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-        let seeds = &[
-            ctx.accounts.multisig.to_account_info().key.as_ref(),
-            &[ctx.accounts.multisig.nonce],
-        ];
-        let signer = &[&seeds[..]];
-        let accounts = ctx.remaining_accounts;
-        for ix in ixs.iter() {
-            solana_program::program::invoke_signed(ix, &accounts, signer)?;
+    // Proposes thawing a frozen token account of a mint whose freeze
+    // authority is the multisig signer.
+    pub fn propose_thaw_token_account<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeMintAuthority<'info>>,
+        token_account: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let (signer, mint) = ctx.accounts.signer_mint(ctx.program_id)?;
+        if mint.freeze_authority != COption::Some(signer) {
+            return Err(ErrorCode::InvalidMintAuthority.into());
         }
+        let ix = spl_token::instruction::thaw_account(
+            &spl_token::ID,
+            &token_account,
+            ctx.accounts.mint.key,
+            &signer,
+            &[],
+        )?;
+        ctx.accounts.transaction.expires_at = expires_at;
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-        // Burn the transaction to ensure one time use.
-        ctx.accounts.transaction.did_execute = true;
+    // Proposes handing the upgrade authority of a program, which the multisig
+    // signer must hold according to its program data account, to
+    // `new_authority`, or making the program immutable if `None`. Subject to
+    // the authority transfer threshold and timelock, see
+    // `set_authority_transfer_policy`.
+    pub fn propose_set_upgrade_authority<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeSetUpgradeAuthority<'info>>,
+        program: Pubkey,
+        new_authority: Option<Pubkey>,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let signer = ctx
+            .accounts
+            .multisig
+            .signer(ctx.accounts.multisig.to_account_info().key, ctx.program_id)?;
+        check_program_data(&program, &ctx.accounts.program_data)?;
+        check_upgrade_authority(&ctx.accounts.program_data, &signer)?;
+        let ix = bpf_loader_upgradeable::set_upgrade_authority(
+            &program,
+            &signer,
+            new_authority.as_ref(),
+        );
+        ctx.accounts.transaction.expires_at = expires_at;
+        ctx.accounts.transaction.authority_transfer = Some(AuthorityTransfer {
+            program,
+            new_authority,
+        });
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-        Ok(())
+    // Proposes delegating a stake account, whose stake authority the multisig
+    // signer must be, to a vote account.
+    pub fn propose_stake_delegate<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        stake_account: Pubkey,
+        vote_account: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        propose_signed_by_multisig(ctx, expires_at, |signer| {
+            stake::delegate(&stake_account, signer, &vote_account)
+        })
     }
-}
 
-#[derive(Accounts)]
-pub struct CreateMultisig<'info> {
-    #[account(init)]
-    multisig: ProgramAccount<'info, Multisig>,
-    rent: Sysvar<'info, Rent>,
-}
+    // Proposes deactivating a stake account, whose stake authority the
+    // multisig signer must be.
+    pub fn propose_stake_deactivate<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        stake_account: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        propose_signed_by_multisig(ctx, expires_at, |signer| {
+            stake::deactivate(&stake_account, signer)
+        })
+    }
 
-#[derive(Accounts)]
-pub struct CreateTransaction<'info> {
-    multisig: ProgramAccount<'info, Multisig>,
-    #[account(init)]
-    transaction: ProgramAccount<'info, Transaction>,
-    // One of the owners. Checked in the handler.
-    #[account(signer)]
-    proposer: AccountInfo<'info>,
-    rent: Sysvar<'info, Rent>,
-}
+    // Proposes moving `lamports` of a stake account, whose stake authority the
+    // multisig signer must be, to a new stake account. The new account must
+    // exist on execution, allocated with `stake::STAKE_ACCOUNT_LEN` bytes and
+    // owned by the stake program.
+    pub fn propose_stake_split<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        stake_account: Pubkey,
+        split_stake_account: Pubkey,
+        lamports: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        propose_signed_by_multisig(ctx, expires_at, |signer| {
+            stake::split(&stake_account, signer, &split_stake_account, lamports)
+        })
+    }
 
-#[derive(Accounts)]
-pub struct Approve<'info> {
-    multisig: ProgramAccount<'info, Multisig>,
-    #[account(seeds = [
-        multisig.to_account_info().key.as_ref(),
-        &[multisig.nonce],
-    ])]
-    multisig_signer: AccountInfo<'info>,
-    #[account(mut, belongs_to = multisig)]
-    transaction: ProgramAccount<'info, Transaction>,
-    // One of the multisig owners. Checked in the handler.
-    #[account(signer)]
-    owner: AccountInfo<'info>,
-}
+    // Proposes withdrawing `lamports` from a stake account, whose withdraw
+    // authority the multisig signer must be, to the recipient.
+    pub fn propose_stake_withdraw<'info>(
+        ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+        stake_account: Pubkey,
+        recipient: Pubkey,
+        lamports: u64,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        propose_signed_by_multisig(ctx, expires_at, |signer| {
+            stake::withdraw(&stake_account, signer, &recipient, lamports)
+        })
+    }
 
-#[account]
-pub struct Multisig {
-    owners: Vec<Pubkey>,
-    threshold: u64,
-    nonce: u8,
-}
+    // Proposes upgrading a program, whose upgrade authority the multisig
+    // signer must be according to its program data account, from a buffer
+    // whose authority is the multisig signer too, recorded in readable fields
+    // for signers to review. The buffer's lamports go to `spill` on execution.
+    pub fn propose_program_upgrade<'info>(
+        ctx: Context<'_, '_, '_, 'info, ProposeProgramUpgrade<'info>>,
+        program: Pubkey,
+        spill: Pubkey,
+        expires_at: Option<i64>,
+    ) -> Result<()> {
+        let signer = ctx
+            .accounts
+            .multisig
+            .signer(ctx.accounts.multisig.to_account_info().key, ctx.program_id)?;
+        let buffer = *ctx.accounts.buffer.key;
+        if buffer_authority(&ctx.accounts.buffer)? != Some(signer) {
+            return Err(ErrorCode::InvalidUpgradeBuffer.into());
+        }
+        check_program_data(&program, &ctx.accounts.program_data)?;
+        check_upgrade_authority(&ctx.accounts.program_data, &signer)?;
+        let ix = bpf_loader_upgradeable::upgrade(&program, &buffer, &signer, &spill);
+        ctx.accounts.transaction.expires_at = expires_at;
+        ctx.accounts.transaction.program_upgrade = Some(ProgramUpgrade { program, buffer });
+        propose(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.proposer,
+            ctx.remaining_accounts,
+            vec![ix],
+        )
+    }
 
-#[account]
-pub struct Transaction {
-    // The multisig account this transaction belongs to.
-    multisig: Pubkey,
-    // Target program to execute against.
-    program_id: Vec<Pubkey>,
-    // Accounts requried for the transaction.
-    accounts: Vec<Vec<TransactionAccount>>,
-    // Instruction data for the transaction.
-    data: Vec<Vec<u8>>,
-    // signers[index] is true iff multisig.owners[index] signed the transaction.
-    signers: Vec<bool>,
-    // Boolean ensuring one time execution.
-    did_execute: bool,
-}
+    // Approves a transaction on behalf of an owner of the multisig. A
+    // transaction that has expired is marked `Expired` instead.
+    pub fn approve(ctx: Context<Approve>) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        if ctx
+            .accounts
+            .transaction
+            .mark_expired(Clock::get()?.unix_timestamp)
+        {
+            return Ok(());
+        }
+        approve_as(
+            &ctx.accounts.multisig,
+            &mut ctx.accounts.transaction,
+            ctx.accounts.owner.key,
+        )?;
+        record_change(
+            ctx.program_id,
+            ctx.accounts.multisig.to_account_info().key,
+            ctx.remaining_accounts,
+            CHANGE_APPROVED,
+            ctx.accounts.owner.key,
+            Some(*ctx.accounts.transaction.to_account_info().key),
+        )
+    }
 
-impl From<&Transaction> for Vec<Instruction> {
-    fn from(tx: &Transaction) -> Vec<Instruction> {
-        let mut instructions: Vec<Instruction> = Vec::new();
-        for (i, _pid) in tx.program_id.iter().enumerate() {
-            instructions.push(Instruction {
-                program_id: tx.program_id[i],
-                accounts: tx.accounts[i].clone().into_iter().map(Into::into).collect(),
-                data: tx.data[i].clone(),
-            })
+    // Approves several transactions of the multisig at once on behalf of an
+    // owner, each checked as by `approve`. The transactions are given as the
+    // first `count` remaining accounts.
+    pub fn batch_approve<'info>(
+        ctx: Context<'_, '_, '_, 'info, BatchApprove<'info>>,
+        count: u8,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let count = count as usize;
+        if count > MAX_BATCH_SIZE as usize {
+            return Err(ErrorCode::BatchTooLarge.into());
         }
-        instructions
+        if ctx.remaining_accounts.len() < count {
+            return Err(ErrorCode::MissingAccount.into());
+        }
+        let multisig = &ctx.accounts.multisig;
+        for info in ctx.remaining_accounts[..count].iter() {
+            if info.owner != ctx.program_id || !info.is_writable {
+                return Err(ErrorCode::InvalidTransaction.into());
+            }
+            let mut tx: ProgramAccount<Transaction> = ProgramAccount::try_from(info)?;
+            if &tx.multisig != multisig.to_account_info().key
+                || tx.owner_set_seqno != multisig.owner_set_seqno
+            {
+                return Err(ErrorCode::InvalidTransaction.into());
+            }
+            approve_as(multisig, &mut tx, ctx.accounts.owner.key)?;
+            // Written right away, so a transaction given twice fails as
+            // already approved.
+            tx.exit(ctx.program_id)?;
+            record_change(
+                ctx.program_id,
+                multisig.to_account_info().key,
+                ctx.remaining_accounts,
+                CHANGE_APPROVED,
+                ctx.accounts.owner.key,
+                Some(*info.key),
+            )?;
+        }
+        Ok(())
     }
-}
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
-pub struct TransactionAccount {
-    pubkey: Pubkey,
-    is_signer: bool,
-    is_writable: bool,
-}
+    // Approves a transaction on behalf of an owner who signed the approval
+    // offline, so that anyone can relay it. The instruction before this one
+    // must be an ed25519 program instruction verifying the owner's signature
+    // of `ed25519::approval_message`. Each signed approval can be relayed
+    // once.
+    pub fn approve_with_signature(ctx: Context<ApproveWithSignature>, owner: Pubkey) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let instructions = &ctx.accounts.instructions;
+        if instructions.key != &sysvar::instructions::ID {
+            return Err(ErrorCode::InvalidSignatureInstruction.into());
+        }
+        let ix = {
+            let data = instructions.try_borrow_data()?;
+            let current = sysvar::instructions::load_current_index(&data) as usize;
+            if current == 0 {
+                return Err(ErrorCode::InvalidSignatureInstruction.into());
+            }
+            sysvar::instructions::load_instruction_at(current - 1, &data)
+                .map_err(|_| ErrorCode::InvalidSignatureInstruction)?
+        };
+        let tx = &mut ctx.accounts.transaction;
+        let message = ed25519::approval_message(
+            ctx.accounts.multisig.to_account_info().key,
+            tx.to_account_info().key,
+            &owner,
+            tx.owner_set_seqno,
+            tx.vault_index,
+            &tx.contents_hash()?,
+        );
+        ed25519::verify(&ix, &owner, &message)?;
+        if tx.signature_approvals.contains(&owner) {
+            return Err(ErrorCode::AlreadyApproved.into());
+        }
+        tx.signature_approvals.push(owner);
+        approve_as(&ctx.accounts.multisig, tx, &owner)?;
+        record_change(
+            ctx.program_id,
+            ctx.accounts.multisig.to_account_info().key,
+            ctx.remaining_accounts,
+            CHANGE_APPROVED,
+            &owner,
+            Some(*tx.to_account_info().key),
+        )
+    }
 
-impl From<TransactionAccount> for AccountMeta {
-    fn from(account: TransactionAccount) -> AccountMeta {
-        match account.is_writable {
-            false => AccountMeta::new_readonly(account.pubkey, account.is_signer),
-            true => AccountMeta::new(account.pubkey, account.is_signer),
+    // Withdraws an owner's approval of a transaction that hasn't executed yet.
+    pub fn revoke_approval(ctx: Context<Approve>) -> Result<()> {
+        debug_assert_approvals_consistent(&ctx.accounts.transaction, &ctx.accounts.multisig);
+        ctx.accounts.transaction.check_pending()?;
+        let owner = ctx.accounts.owner.key;
+        if !ctx.accounts.multisig.owners.contains(owner) {
+            return Err(ErrorCode::InvalidOwner.into());
         }
+
+        let tx = &mut ctx.accounts.transaction;
+        tx.revoke(owner);
+        tx.update_threshold_reached(&ctx.accounts.multisig, Clock::get()?.unix_timestamp)?;
+
+        emit!(ApprovalRevoked {
+            multisig: tx.multisig,
+            transaction: *tx.to_account_info().key,
+            index: tx.index,
+            owner: *owner,
+        });
+        Ok(())
     }
-}
 
-#[error]
-pub enum ErrorCode {
-    #[msg("The given owner is not part of this multisig.")]
-    InvalidOwner,
-    #[msg("Not enough owners signed this transaction.")]
-    NotEnoughSigners,
-    #[msg("Cannot delete a transaction that has been signed by an owner.")]
-    TransactionAlreadySigned,
-    #[msg("Overflow when adding.")]
-    Overflow,
-    #[msg("Cannot delete a transaction the owner did not create.")]
-    UnableToDelete,
+    // Rejects a transaction on behalf of an owner, withdrawing their approval.
+    // Once so many owners have rejected that the threshold the transaction
+    // needs can no longer be reached, the transaction is cancelled. The
+    // instruction buffer of a transaction proposed from one must be given as a
+    // remaining account.
+    pub fn reject(ctx: Context<Approve>) -> Result<()> {
+        debug_assert_approvals_consistent(&ctx.accounts.transaction, &ctx.accounts.multisig);
+        let owner = ctx.accounts.owner.key;
+        let tx = &mut ctx.accounts.transaction;
+        tx.check_pending()?;
+        let multisig = &ctx.accounts.multisig;
+        multisig.check_permission(owner, PERMISSION_APPROVE)?;
+
+        tx.reject(owner);
+        tx.update_threshold_reached(multisig, Clock::get()?.unix_timestamp)?;
+
+        let mut rejected_weight: u64 = 0;
+        for rejector in tx.rejectors.iter() {
+            rejected_weight = rejected_weight
+                .checked_add(multisig.approval_weight_of(rejector))
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        // Instructions proposed from a buffer are read from it, given as a
+        // remaining account.
+        let accounts = TransactionAccountMap::new(ctx.remaining_accounts);
+        let mut policy_ixs = tx.instructions(&accounts)?;
+        policy_ixs.extend(config_instructions(
+            ctx.program_id,
+            multisig,
+            &tx.config_actions,
+        )?);
+        // Weights may have changed since earlier rejections.
+        let reachable = multisig.total_weight()?.saturating_sub(rejected_weight);
+        if reachable < multisig.threshold_for(&policy_ixs) {
+            tx.status = TransactionStatus::Cancelled;
+            tx.finalized_at = Some(Clock::get()?.unix_timestamp);
+        }
+
+        record_change(
+            ctx.program_id,
+            multisig.to_account_info().key,
+            ctx.remaining_accounts,
+            CHANGE_REJECTED,
+            owner,
+            Some(*tx.to_account_info().key),
+        )
+    }
+
+    // Cancels a transaction that hasn't executed yet, however many owners
+    // approved it. Only callable by the multisig's veto authority, as a last
+    // resort against a compromised quorum.
+    pub fn veto(ctx: Context<Veto>) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        if multisig.veto_authority != Some(*ctx.accounts.veto_authority.key) {
+            return Err(ErrorCode::InvalidVetoAuthority.into());
+        }
+        let tx = &mut ctx.accounts.transaction;
+        match tx.status {
+            TransactionStatus::Executed => return Err(ErrorCode::AlreadyExecuted.into()),
+            TransactionStatus::Cancelled => return Err(ErrorCode::TransactionCancelled.into()),
+            TransactionStatus::Expired => return Err(ErrorCode::TransactionExpired.into()),
+            _ => {}
+        }
+        tx.status = TransactionStatus::Cancelled;
+        tx.finalized_at = Some(Clock::get()?.unix_timestamp);
+
+        emit!(TransactionVetoed {
+            multisig: tx.multisig,
+            transaction: *tx.to_account_info().key,
+            index: tx.index,
+        });
+        record_change(
+            ctx.program_id,
+            &tx.multisig,
+            ctx.remaining_accounts,
+            CHANGE_VETOED,
+            ctx.accounts.veto_authority.key,
+            Some(*tx.to_account_info().key),
+        )
+    }
+
+    // Freezes the multisig, blocking proposals, approvals and execution until
+    // it is unfrozen. Callable by the freeze guardian, or by `freeze_threshold`
+    // owners signing together. The signers are given as remaining accounts.
+    pub fn freeze(ctx: Context<Freeze>) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        let by_guardian = ctx
+            .remaining_accounts
+            .iter()
+            .any(|a| a.is_signer && multisig.freeze_guardian == Some(*a.key));
+        let owners = signing_owners(multisig, ctx.remaining_accounts);
+        let by_owners =
+            multisig.freeze_threshold > 0 && owners.len() as u64 >= multisig.freeze_threshold;
+        if !by_guardian && !by_owners {
+            return Err(ErrorCode::NotEnoughSigners.into());
+        }
+        multisig.frozen = true;
+
+        emit!(FreezeChanged {
+            multisig: *multisig.to_account_info().key,
+            frozen: true,
+        });
+        Ok(())
+    }
+
+    // Unfreezes the multisig. The owners vote by signing together, and their
+    // summed weight must reach the threshold. The signers are given as
+    // remaining accounts.
+    pub fn unfreeze(ctx: Context<Freeze>) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let multisig = &mut ctx.accounts.multisig;
+        let mut weight: u64 = 0;
+        for owner in signing_owners(multisig, ctx.remaining_accounts) {
+            weight = weight
+                .checked_add(multisig.approval_weight_of(&owner))
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        if weight < multisig.threshold {
+            return Err(ErrorCode::NotEnoughSigners.into());
+        }
+        multisig.frozen = false;
+
+        emit!(FreezeChanged {
+            multisig: *multisig.to_account_info().key,
+            frozen: false,
+        });
+        Ok(())
+    }
+
+    // Starts replacing the owners and threshold of a multisig whose owners
+    // have been inactive, i.e. haven't executed anything, for the recovery
+    // inactivity period. Needs `guardian_threshold` guardians signing
+    // together, given as remaining accounts. The recovery can be completed
+    // once the recovery delay has passed, unless an owner cancels it first.
+    pub fn initiate_recovery(
+        ctx: Context<Recovery>,
+        owners: Vec<Pubkey>,
+        threshold: u64,
+    ) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        check_new_multisig(&owners, threshold)?;
+        let multisig = &mut ctx.accounts.multisig;
+        if multisig.guardians.is_empty() {
+            return Err(ErrorCode::InvalidRecoveryConfig.into());
+        }
+        let mut guardians: Vec<&Pubkey> = Vec::new();
+        for a in ctx.remaining_accounts.iter().filter(|a| a.is_signer) {
+            if multisig.guardians.contains(a.key) && !guardians.contains(&a.key) {
+                guardians.push(a.key);
+            }
+        }
+        if (guardians.len() as u64) < multisig.guardian_threshold {
+            return Err(ErrorCode::NotEnoughSigners.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let inactive_for = now
+            .checked_sub(multisig.last_executed_at)
+            .ok_or(ErrorCode::Overflow)?;
+        if inactive_for < multisig.recovery_inactivity_period {
+            return Err(ErrorCode::OwnersStillActive.into());
+        }
+        let completes_at = now
+            .checked_add(multisig.recovery_delay)
+            .ok_or(ErrorCode::Overflow)?;
+
+        emit!(RecoveryInitiated {
+            multisig: *multisig.to_account_info().key,
+            owners: owners.clone(),
+            threshold,
+            completes_at,
+        });
+        multisig.pending_recovery = Some(PendingRecovery {
+            owners,
+            threshold,
+            started_at: now,
+        });
+        Ok(())
+    }
+
+    // Replaces the owners and threshold with the pending recovery's once its
+    // delay has passed, provided the multisig executed nothing in between.
+    // The weights, permissions, required approvers, approval groups and
+    // spending limit of the old owners are dropped. Callable by anyone.
+    pub fn complete_recovery(ctx: Context<Recovery>) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let multisig = &mut ctx.accounts.multisig;
+        let recovery = multisig
+            .pending_recovery
+            .take()
+            .ok_or(ErrorCode::NoPendingRecovery)?;
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now
+            .checked_sub(recovery.started_at)
+            .ok_or(ErrorCode::Overflow)?;
+        if elapsed < multisig.recovery_delay {
+            return Err(ErrorCode::RecoveryDelayNotPassed.into());
+        }
+        if multisig.last_executed_at >= recovery.started_at {
+            return Err(ErrorCode::OwnersStillActive.into());
+        }
+
+        multisig.owners = recovery.owners;
+        multisig.threshold = recovery.threshold;
+        multisig.pending_owners.clear();
+        multisig.owner_weights.clear();
+        multisig.owner_permissions.clear();
+        multisig.required_approvers.clear();
+        multisig.approval_groups.clear();
+        multisig.threshold_tiers.clear();
+        multisig.threshold_percent = 0;
+        multisig.pending_threshold = 0;
+        multisig.pending_tier_thresholds.clear();
+        multisig.pending_authority_transfer_threshold = 0;
+        multisig.spending_limit = None;
+        // The old weights are gone, so each new owner counts once.
+        if multisig.authority_transfer_threshold > multisig.owners.len() as u64 {
+            multisig.authority_transfer_threshold = multisig.owners.len() as u64;
+        }
+        if multisig.freeze_threshold > multisig.owners.len() as u64 {
+            multisig.freeze_threshold = multisig.owners.len() as u64;
+        }
+        // Give the new owners a full inactivity period.
+        multisig.last_executed_at = now;
+        multisig.owner_set_seqno += 1;
+
+        emit!(OwnersChanged {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+            owner_set_seqno: multisig.owner_set_seqno,
+        });
+        Ok(())
+    }
+
+    // Cancels the pending recovery. Callable by any owner, since an owner
+    // signing shows the owners haven't lost their keys.
+    pub fn cancel_recovery(ctx: Context<CancelRecovery>) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        if !multisig.owners.contains(ctx.accounts.owner.key) {
+            return Err(ErrorCode::InvalidOwner.into());
+        }
+        if multisig.pending_recovery.take().is_none() {
+            return Err(ErrorCode::NoPendingRecovery.into());
+        }
+        Ok(())
+    }
+
+    // Closes a transaction nobody but its proposer has signed yet, returning its
+    // rent to the proposer.
+    pub fn delete_transaction(ctx: Context<DeleteTransaction>) -> Result<()> {
+        let tx: ProgramAccount<Transaction> = ProgramAccount::try_from(&ctx.accounts.transaction)?;
+        if &tx.multisig != ctx.accounts.multisig.to_account_info().key {
+            return Err(ErrorCode::InvalidTransaction.into());
+        }
+        if &tx.proposer != ctx.accounts.proposer.key {
+            return Err(ErrorCode::UnableToDelete.into());
+        }
+        if tx.status == TransactionStatus::Executed {
+            return Err(ErrorCode::AlreadyExecuted.into());
+        }
+        let co_signed = tx
+            .approvals
+            .iter()
+            .any(|a| &a.owner != ctx.accounts.proposer.key);
+        if co_signed {
+            return Err(ErrorCode::TransactionAlreadySigned.into());
+        }
+
+        close_account(&ctx.accounts.transaction, &ctx.accounts.proposer)
+    }
+
+    // Records the compute unit limit and price, in micro-lamports per unit,
+    // the execution should request with `ComputeBudget` instructions, which
+    // must be top level and so can't be part of the transaction. A hint for
+    // clients and cranks only, not enforced. Only the proposer may.
+    pub fn set_transaction_compute_budget(
+        ctx: Context<EditTransaction>,
+        compute_unit_limit: Option<u32>,
+        compute_unit_price: Option<u64>,
+    ) -> Result<()> {
+        let tx = &mut ctx.accounts.transaction;
+        tx.check_pending()?;
+        tx.compute_unit_limit = compute_unit_limit;
+        tx.compute_unit_price = compute_unit_price;
+
+        emit!(ComputeBudgetChanged {
+            multisig: tx.multisig,
+            transaction: *tx.to_account_info().key,
+            index: tx.index,
+            compute_unit_limit,
+            compute_unit_price,
+        });
+        Ok(())
+    }
+
+    // Restricts when a transaction may execute, from `not_before` until
+    // `not_after`, both unix timestamps, or lifts either bound if `None`.
+    // Unlike `expires_at`, the window doesn't restrict approvals. Only the
+    // proposer may, and only until someone else approves.
+    pub fn set_transaction_schedule(
+        ctx: Context<EditTransaction>,
+        not_before: Option<i64>,
+        not_after: Option<i64>,
+    ) -> Result<()> {
+        if let (Some(not_before), Some(not_after)) = (not_before, not_after) {
+            if not_before >= not_after {
+                return Err(ErrorCode::InvalidExecutionWindow.into());
+            }
+        }
+        let tx = &mut ctx.accounts.transaction;
+        tx.check_pending()?;
+        let proposer = ctx.accounts.proposer.key;
+        if tx.approvals.iter().any(|a| &a.owner != proposer) {
+            return Err(ErrorCode::TransactionAlreadySigned.into());
+        }
+        tx.not_before = not_before;
+        tx.not_after = not_after;
+        Ok(())
+    }
+
+    // Moves the earliest time a transaction may execute, its `not_before`, to
+    // `new_eta`, e.g. to wait for an audit or to act on an exploit sooner. The
+    // new ETA must be at least the multisig's timelock and at most
+    // `MAX_ETA_DELAY` seconds from now, and before `not_after`. Only the
+    // proposer may, and only until someone else approves.
+    pub fn set_transaction_eta(ctx: Context<EditTransaction>, new_eta: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let earliest = now
+            .checked_add(ctx.accounts.multisig.timelock_seconds)
+            .ok_or(ErrorCode::Overflow)?;
+        let latest = now.checked_add(MAX_ETA_DELAY).ok_or(ErrorCode::Overflow)?;
+        if new_eta < earliest || new_eta > latest {
+            return Err(ErrorCode::InvalidEta.into());
+        }
+        let tx = &mut ctx.accounts.transaction;
+        tx.check_pending()?;
+        let proposer = ctx.accounts.proposer.key;
+        if tx.approvals.iter().any(|a| &a.owner != proposer) {
+            return Err(ErrorCode::TransactionAlreadySigned.into());
+        }
+        if matches!(tx.not_after, Some(not_after) if new_eta >= not_after) {
+            return Err(ErrorCode::InvalidExecutionWindow.into());
+        }
+        let old_eta = tx.not_before;
+        tx.not_before = Some(new_eta);
+
+        emit!(EtaUpdated {
+            multisig: tx.multisig,
+            transaction: *tx.to_account_info().key,
+            old_eta,
+            new_eta,
+        });
+        Ok(())
+    }
+
+    // Makes a transaction executable up to `max_executions` times, at least
+    // `min_execution_interval` seconds apart, e.g. for monthly payments. Only
+    // the proposer may, and only until someone else approves.
+    pub fn set_transaction_recurrence(
+        ctx: Context<EditTransaction>,
+        max_executions: u32,
+        min_execution_interval: i64,
+    ) -> Result<()> {
+        if min_execution_interval < 0 {
+            return Err(ErrorCode::InvalidRecurrence.into());
+        }
+        let tx = &mut ctx.accounts.transaction;
+        tx.check_pending()?;
+        let proposer = ctx.accounts.proposer.key;
+        if tx.approvals.iter().any(|a| &a.owner != proposer) {
+            return Err(ErrorCode::TransactionAlreadySigned.into());
+        }
+        tx.max_executions = max_executions;
+        tx.min_execution_interval = min_execution_interval;
+        Ok(())
+    }
+
+    // Sets the memo explaining a transaction to its signers, or clears it if
+    // `None`. Only the proposer may, and only until someone else approves.
+    pub fn set_transaction_memo(ctx: Context<EditTransaction>, memo: Option<String>) -> Result<()> {
+        if matches!(&memo, Some(memo) if memo.len() > MAX_MEMO_LEN) {
+            return Err(ErrorCode::MemoTooLong.into());
+        }
+        let tx = &mut ctx.accounts.transaction;
+        tx.check_pending()?;
+        let proposer = ctx.accounts.proposer.key;
+        if tx.approvals.iter().any(|a| &a.owner != proposer) {
+            return Err(ErrorCode::TransactionAlreadySigned.into());
+        }
+        tx.memo = memo;
+        Ok(())
+    }
+
+    // Makes a transaction executable only once the given transaction of the
+    // same multisig has executed, or removes the dependency if `None`. Only
+    // the proposer may, and only until someone else approves.
+    pub fn set_transaction_dependency(
+        ctx: Context<EditTransaction>,
+        depends_on: Option<Pubkey>,
+    ) -> Result<()> {
+        let tx = &mut ctx.accounts.transaction;
+        if depends_on == Some(*tx.to_account_info().key) {
+            return Err(ErrorCode::InvalidDependency.into());
+        }
+        tx.check_pending()?;
+        let proposer = ctx.accounts.proposer.key;
+        if tx.approvals.iter().any(|a| &a.owner != proposer) {
+            return Err(ErrorCode::TransactionAlreadySigned.into());
+        }
+        tx.depends_on = depends_on;
+        Ok(())
+    }
+
+    // Closes an executed, cancelled or expired transaction, sending its rent to
+    // the multisig's `close_destination`, or the proposer if not set. Expired
+    // transactions may be closed by anyone. Otherwise any owner may close, and
+    // anyone else once `CLOSE_GRACE_PERIOD` has passed.
+    pub fn close_transaction(ctx: Context<CloseTransaction>) -> Result<()> {
+        let tx: ProgramAccount<Transaction> = ProgramAccount::try_from(&ctx.accounts.transaction)?;
+        let multisig = &ctx.accounts.multisig;
+        if &tx.multisig != multisig.to_account_info().key {
+            return Err(ErrorCode::InvalidTransaction.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        if !tx.is_expired(now) {
+            let finalized_at = tx.finalized_at.ok_or(ErrorCode::TransactionNotFinalized)?;
+            if !multisig.owners.contains(ctx.accounts.closer.key) {
+                let elapsed = now.checked_sub(finalized_at).ok_or(ErrorCode::Overflow)?;
+                if elapsed < CLOSE_GRACE_PERIOD {
+                    return Err(ErrorCode::CloseTooEarly.into());
+                }
+            }
+        }
+        let destination = multisig.close_destination.unwrap_or(tx.proposer);
+        if &destination != ctx.accounts.destination.key {
+            return Err(ErrorCode::InvalidCloseDestination.into());
+        }
+
+        close_account(&ctx.accounts.transaction, &ctx.accounts.destination)
+    }
+
+    // Creates the history of the given multisig at `pda::history`, paid for by
+    // anyone. Executions given it as a remaining account record themselves in
+    // it, so executed transactions can be closed without losing their trail.
+    pub fn init_transaction_history(ctx: Context<InitTransactionHistory>) -> Result<()> {
+        let multisig_key = *ctx.accounts.multisig.to_account_info().key;
+        let (address, bump) = pda::history(&multisig_key, ctx.program_id);
+        if &address != ctx.accounts.history.key {
+            return Err(ErrorCode::InvalidHistory.into());
+        }
+        let history = TransactionHistory {
+            multisig: multisig_key,
+            next: 0,
+            entries: Vec::new(),
+        };
+        let space = TransactionHistory::SPACE;
+        let ix = system_instruction::create_account(
+            ctx.accounts.payer.key,
+            &address,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            ctx.program_id,
+        );
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.payer.clone(),
+                ctx.accounts.history.clone(),
+                ctx.accounts.system_program.clone(),
+            ],
+            &[&[multisig_key.as_ref(), pda::HISTORY_SEED, &[bump]]],
+        )?;
+        let mut data = ctx.accounts.history.try_borrow_mut_data()?;
+        history.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    // Creates the changelog of the given multisig at `pda::changelog`, paid for
+    // by anyone. Proposals, approvals, rejections, vetoes, executions and
+    // owner changes given it as a remaining account record themselves in it.
+    pub fn init_changelog(ctx: Context<InitChangelog>) -> Result<()> {
+        let multisig_key = *ctx.accounts.multisig.to_account_info().key;
+        let (address, bump) = pda::changelog(&multisig_key, ctx.program_id);
+        if &address != ctx.accounts.changelog.key {
+            return Err(ErrorCode::InvalidChangelog.into());
+        }
+        let changelog = MultisigChangelog {
+            entries: [ChangelogEntry::default(); CHANGELOG_LEN],
+            head: 0,
+        };
+        let space = MultisigChangelog::SPACE;
+        let ix = system_instruction::create_account(
+            ctx.accounts.payer.key,
+            &address,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            ctx.program_id,
+        );
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.payer.clone(),
+                ctx.accounts.changelog.clone(),
+                ctx.accounts.system_program.clone(),
+            ],
+            &[&[multisig_key.as_ref(), pda::CHANGELOG_SEED, &[bump]]],
+        )?;
+        let mut data = ctx.accounts.changelog.try_borrow_mut_data()?;
+        changelog.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    // Executes the given transaction if threshold owners have signed it. Anyone
+    // may execute, paying any execution fee, and must pass every account the
+    // transaction's instructions need as remaining accounts. Instructions
+    // already run by `execute_instruction` are skipped. A transaction that has
+    // expired is marked `Expired` instead. Each completed run is recorded in
+    // the multisig's `TransactionHistory` if given as a remaining account.
+    pub fn execute_transaction<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteTransaction<'info>>,
+    ) -> Result<()> {
+        if ctx
+            .accounts
+            .transaction
+            .mark_expired(Clock::get()?.unix_timestamp)
+        {
+            return Ok(());
+        }
+        execute_instructions(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &ctx.accounts.multisig_signer,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.executor,
+            ctx.remaining_accounts,
+            usize::MAX,
+        )
+    }
+
+    // Executes one instruction of the given transaction, which must be the
+    // next one not executed yet, for transactions too expensive to execute at
+    // once. Checked, and marked `Expired`, the same way as
+    // `execute_transaction`.
+    pub fn execute_instruction<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteTransaction<'info>>,
+        index: u8,
+    ) -> Result<()> {
+        if ctx
+            .accounts
+            .transaction
+            .mark_expired(Clock::get()?.unix_timestamp)
+        {
+            return Ok(());
+        }
+        if index != ctx.accounts.transaction.next_instruction {
+            return Err(ErrorCode::InvalidInstructionIndex.into());
+        }
+        execute_instructions(
+            ctx.program_id,
+            &mut ctx.accounts.multisig,
+            &ctx.accounts.multisig_signer,
+            &mut ctx.accounts.transaction,
+            &ctx.accounts.executor,
+            ctx.remaining_accounts,
+            1,
+        )
+    }
+
+    // Executes several approved transactions of the multisig at once, each
+    // checked as by `execute_transaction`, so they either all execute or none
+    // does. The transactions are given, in order, as the first `count`
+    // remaining accounts, followed by the accounts their instructions need.
+    // A transaction may depend on an earlier one of the same batch.
+    pub fn execute_batch<'info>(
+        ctx: Context<'_, '_, '_, 'info, ExecuteBatch<'info>>,
+        count: u8,
+    ) -> Result<()> {
+        let count = count as usize;
+        if count > MAX_BATCH_SIZE as usize {
+            return Err(ErrorCode::BatchTooLarge.into());
+        }
+        if ctx.remaining_accounts.len() < count {
+            return Err(ErrorCode::MissingAccount.into());
+        }
+        let tx_accounts = &ctx.remaining_accounts[..count];
+        let multisig_key = *ctx.accounts.multisig.to_account_info().key;
+        for info in tx_accounts.iter() {
+            if info.owner != ctx.program_id {
+                return Err(ErrorCode::InvalidTransaction.into());
+            }
+            let mut tx: ProgramAccount<Transaction> = ProgramAccount::try_from(info)?;
+            // An earlier transaction of the batch may have changed the owners.
+            if tx.multisig != multisig_key
+                || tx.owner_set_seqno != ctx.accounts.multisig.owner_set_seqno
+            {
+                return Err(ErrorCode::InvalidTransaction.into());
+            }
+            execute_instructions(
+                ctx.program_id,
+                &mut ctx.accounts.multisig,
+                &ctx.accounts.multisig_signer,
+                &mut tx,
+                &ctx.accounts.executor,
+                ctx.remaining_accounts,
+                usize::MAX,
+            )?;
+            // Written right away, so a transaction given twice fails as
+            // already executed, and the next one's reload of the multisig
+            // sees this one's changes to it.
+            tx.exit(ctx.program_id)?;
+            ctx.accounts.multisig.exit(ctx.program_id)?;
+        }
+        Ok(())
+    }
+
+    // Checks the remaining accounts cover every account, and program, needed
+    // to execute the given transaction, so clients can confirm they are ready
+    // to execute before calling `execute_transaction`.
+    pub fn validate_accounts_present<'info>(
+        ctx: Context<'_, '_, '_, 'info, ValidateAccounts<'info>>,
+    ) -> Result<()> {
+        let accounts = TransactionAccountMap::new(ctx.remaining_accounts);
+        let ixs = ctx.accounts.transaction.instructions(&accounts)?;
+        for ix in ixs.iter() {
+            accounts.instruction_accounts(ix)?;
+        }
+        ctx.accounts.transaction.accounts_validated_at = Some(Clock::get()?.unix_timestamp);
+        Ok(())
+    }
+
+    // Transfers lamports from the multisig signer without a proposal, within
+    // the spending limit of the signing owner.
+    pub fn spend_lamports(ctx: Context<SpendLamports>, amount: u64) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        ctx.accounts.multisig.check_not_frozen()?;
+        let now = Clock::get()?.unix_timestamp;
+        let owner = ctx.accounts.owner.key;
+        ctx.accounts
+            .multisig
+            .spend_allowance(owner, None, amount, now)?;
+
+        let ix = system_instruction::transfer(
+            ctx.accounts.multisig_signer.key,
+            ctx.accounts.destination.key,
+            amount,
+        );
+        let seeds = &[
+            ctx.accounts.multisig.to_account_info().key.as_ref(),
+            &[ctx.accounts.multisig.nonce],
+        ];
+        let signer = &[&seeds[..]];
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.multisig_signer.clone(),
+                ctx.accounts.destination.clone(),
+                ctx.accounts.system_program.clone(),
+            ],
+            signer,
+        )?;
+        Ok(())
+    }
+
+    // Transfers tokens from a token account of the multisig signer without a
+    // proposal, within the spending limit of the signing owner.
+    pub fn spend_tokens(ctx: Context<SpendTokens>, amount: u64) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        ctx.accounts.multisig.check_not_frozen()?;
+        let source = spl_token::state::Account::unpack(&ctx.accounts.source.try_borrow_data()?)?;
+        if &source.owner != ctx.accounts.multisig_signer.key {
+            return Err(ErrorCode::InvalidTokenAccountOwner.into());
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let owner = ctx.accounts.owner.key;
+        ctx.accounts
+            .multisig
+            .spend_allowance(owner, Some(source.mint), amount, now)?;
+
+        let ix = spl_token::instruction::transfer(
+            &spl_token::ID,
+            ctx.accounts.source.key,
+            ctx.accounts.destination.key,
+            ctx.accounts.multisig_signer.key,
+            &[],
+            amount,
+        )?;
+        let seeds = &[
+            ctx.accounts.multisig.to_account_info().key.as_ref(),
+            &[ctx.accounts.multisig.nonce],
+        ];
+        let signer = &[&seeds[..]];
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.source.clone(),
+                ctx.accounts.destination.clone(),
+                ctx.accounts.multisig_signer.clone(),
+                ctx.accounts.token_program.clone(),
+            ],
+            signer,
+        )?;
+        Ok(())
+    }
+
+    // Sets the owners field on the multisig, dropping the weights of removed
+    // owners and lowering the threshold if it is larger than the new total
+    // weight. Keys that aren't owners yet only become owners once they
+    // `accept_ownership`, and don't count towards the total weight until then.
+    // Only callable by the multisig itself.
+    pub fn set_owners(ctx: Context<Auth>, owners: Vec<Pubkey>) -> Result<()> {
+        replace_owners(&mut ctx.accounts.multisig, owners)
+    }
+
+    // Makes a key added by `set_owners` an owner. The key must sign, proving
+    // it is the intended one. Raises the thresholds `set_owners` had to lower,
+    // if any, back towards the ones asked for.
+    pub fn accept_ownership(ctx: Context<AcceptOwnership>) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let new_owner = ctx.accounts.new_owner.key;
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.check_not_frozen()?;
+        if !multisig.pending_owners.contains(new_owner) {
+            return Err(ErrorCode::NotPendingOwner.into());
+        }
+        multisig.pending_owners.retain(|o| o != new_owner);
+        multisig.owners.push(*new_owner);
+        let total_weight = multisig.total_weight()?;
+        let m = &mut **multisig;
+        raise_threshold(&mut m.threshold, &mut m.pending_threshold, total_weight);
+        for (t, pending) in m
+            .threshold_tiers
+            .iter_mut()
+            .zip(m.pending_tier_thresholds.iter_mut())
+        {
+            raise_threshold(&mut t.threshold, pending, total_weight);
+        }
+        if m.pending_tier_thresholds.iter().all(|p| *p == 0) {
+            m.pending_tier_thresholds.clear();
+        }
+        raise_threshold(
+            &mut m.authority_transfer_threshold,
+            &mut m.pending_authority_transfer_threshold,
+            total_weight,
+        );
+        multisig.apply_threshold_percent()?;
+        multisig.owner_set_seqno += 1;
+
+        emit!(OwnersChanged {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+            owner_set_seqno: multisig.owner_set_seqno,
+        });
+        record_change(
+            ctx.program_id,
+            multisig.to_account_info().key,
+            ctx.remaining_accounts,
+            CHANGE_OWNER_ACCEPTED,
+            new_owner,
+            None,
+        )
+    }
+
+    // Replaces the signing owner's key with a new one, which must sign too,
+    // keeping its weight, permissions and other settings. Invalidates the
+    // pending transactions like any other owner change.
+    pub fn rotate_owner_key(ctx: Context<RotateOwnerKey>) -> Result<()> {
+        check_global_pause(ctx.program_id, ctx.remaining_accounts)?;
+        let old = *ctx.accounts.owner.key;
+        let new = *ctx.accounts.new_owner.key;
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.check_not_frozen()?;
+        if !multisig.owners.contains(&old) {
+            return Err(ErrorCode::InvalidOwner.into());
+        }
+        if multisig.owners.contains(&new) || multisig.pending_owners.contains(&new) {
+            return Err(ErrorCode::DuplicateOwner.into());
+        }
+        let rotate = |key: &mut Pubkey| {
+            if *key == old {
+                *key = new;
+            }
+        };
+        multisig.owners.iter_mut().for_each(rotate);
+        multisig.required_approvers.iter_mut().for_each(rotate);
+        for g in multisig.approval_groups.iter_mut() {
+            g.members.iter_mut().for_each(rotate);
+        }
+        for w in multisig.owner_weights.iter_mut() {
+            rotate(&mut w.owner);
+        }
+        for p in multisig.owner_permissions.iter_mut() {
+            rotate(&mut p.owner);
+        }
+        if let Some(limit) = multisig.spending_limit.as_mut() {
+            rotate(&mut limit.owner);
+        }
+        multisig.owner_set_seqno += 1;
+
+        emit!(OwnersChanged {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+            owner_set_seqno: multisig.owner_set_seqno,
+        });
+        record_change(
+            ctx.program_id,
+            multisig.to_account_info().key,
+            ctx.remaining_accounts,
+            CHANGE_OWNER_KEY_ROTATED,
+            &new,
+            None,
+        )
+    }
+
+    // Sets the `PERMISSION_*` mask of some owners, and whether only owners
+    // with `PERMISSION_EXECUTE` may execute. Owners without a mask have every
+    // permission. Only callable by the multisig itself.
+    pub fn set_owner_permissions(
+        ctx: Context<Auth>,
+        owner_permissions: Vec<OwnerPermissions>,
+        restrict_execution: bool,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        for p in owner_permissions.iter() {
+            if !multisig.owners.contains(&p.owner) {
+                return Err(ErrorCode::InvalidOwner.into());
+            }
+        }
+        multisig.owner_permissions = owner_permissions;
+        multisig.restrict_execution = restrict_execution;
+        multisig.apply_threshold_percent()?;
+        if multisig.threshold > multisig.total_weight()? {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+        multisig.check_threshold_tiers()?;
+        // Required approvers must stay able to approve.
+        for r in multisig.required_approvers.iter() {
+            multisig.check_permission(r, PERMISSION_APPROVE)?;
+        }
+        multisig.check_approval_groups()?;
+        multisig.owner_set_seqno += 1;
+        Ok(())
+    }
+
+    // Sets the voting weight of some owners. Owners without a weight count
+    // once. Only callable by the multisig itself.
+    pub fn set_owner_weights(ctx: Context<Auth>, owner_weights: Vec<OwnerWeight>) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        for w in owner_weights.iter() {
+            if !multisig.owners.contains(&w.owner) {
+                return Err(ErrorCode::InvalidOwner.into());
+            }
+        }
+        multisig.owner_weights = owner_weights;
+        multisig.apply_threshold_percent()?;
+        if multisig.threshold > multisig.total_weight()? {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+        multisig.check_threshold_tiers()?;
+        multisig.owner_set_seqno += 1;
+        Ok(())
+    }
+
+    // Changes the number of owners required to execute a transaction. Only
+    // callable by the multisig itself.
+    pub fn change_threshold(ctx: Context<Auth>, threshold: u64) -> Result<()> {
+        update_threshold(&mut ctx.accounts.multisig, threshold)
+    }
+
+    // Makes the threshold the given percentage of the owners' total weight,
+    // rounded up, kept up to date as owners change. `change_threshold` sets an
+    // absolute threshold again. Only callable by the multisig itself.
+    pub fn set_threshold_percent(ctx: Context<Auth>, threshold_percent: u8) -> Result<()> {
+        if threshold_percent == 0 || threshold_percent > 100 {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.threshold_percent = threshold_percent;
+        multisig.pending_threshold = 0;
+        multisig.apply_threshold_percent()?;
+        multisig.owner_set_seqno += 1;
+
+        emit!(OwnersChanged {
+            multisig: *multisig.to_account_info().key,
+            owners: multisig.owners.clone(),
+            threshold: multisig.threshold,
+            owner_set_seqno: multisig.owner_set_seqno,
+        });
+        Ok(())
+    }
+
+    // Sets the programs whose instructions are restricted to a set of known
+    // discriminants. Only callable by the multisig itself.
+    pub fn set_known_programs(ctx: Context<Auth>, known_programs: Vec<KnownProgram>) -> Result<()> {
+        ctx.accounts.multisig.known_programs = known_programs;
+        Ok(())
+    }
+
+    // Sets the programs the `VALIDATOR_ALLOWLIST` policy allows any
+    // instruction of, e.g. the System, Token and Stake programs. Only callable
+    // by the multisig itself.
+    pub fn set_allowed_programs(ctx: Context<Auth>, allowed_programs: Vec<Pubkey>) -> Result<()> {
+        ctx.accounts.multisig.allowed_programs = allowed_programs;
+        Ok(())
+    }
+
+    // Sets the rules forbidding transactions from invoking some instructions,
+    // e.g. `SetAuthority` on an upgrade authority the multisig holds. Only
+    // callable by the multisig itself.
+    pub fn set_deny_rules(ctx: Context<Auth>, deny_rules: Vec<DenyRule>) -> Result<()> {
+        if deny_rules
+            .iter()
+            .any(|r| r.data_prefix.len() > MAX_INSTRUCTION_DATA_LEN)
+        {
+            return Err(ErrorCode::InvalidDenyRule.into());
+        }
+        ctx.accounts.multisig.deny_rules = deny_rules;
+        Ok(())
+    }
+
+    // Sets the thresholds required instead of the multisig's for transactions
+    // invoking some instructions, e.g. a higher one for program upgrades.
+    // Lower tiers than the multisig's threshold have no effect. Only callable
+    // by the multisig itself.
+    pub fn set_threshold_tiers(
+        ctx: Context<Auth>,
+        threshold_tiers: Vec<ThresholdTier>,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.threshold_tiers = threshold_tiers;
+        multisig.pending_tier_thresholds.clear();
+        multisig.check_threshold_tiers()?;
+        Ok(())
+    }
+
+    // Sets the threshold, if higher than the multisig's, and the timelock, if
+    // longer, required by transactions handing off an upgrade authority
+    // through the upgradeable loader's `SetAuthority`, whether proposed with
+    // `propose_set_upgrade_authority` or not. Zero disables either. Only
+    // callable by the multisig itself.
+    pub fn set_authority_transfer_policy(
+        ctx: Context<Auth>,
+        authority_transfer_threshold: u64,
+        authority_transfer_timelock: i64,
+    ) -> Result<()> {
+        if authority_transfer_timelock < 0 {
+            return Err(ErrorCode::InvalidTimelock.into());
+        }
+        let multisig = &mut ctx.accounts.multisig;
+        if authority_transfer_threshold > multisig.total_weight()? {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+        multisig.authority_transfer_threshold = authority_transfer_threshold;
+        multisig.pending_authority_transfer_threshold = 0;
+        multisig.authority_transfer_timelock = authority_transfer_timelock;
+        Ok(())
+    }
+
+    // Sets the validators evaluated against every new transaction. Only
+    // callable by the multisig itself.
+    pub fn set_validators(
+        ctx: Context<Auth>,
+        validators_enabled: u32,
+        max_transfer_lamports: u64,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.validators_enabled = validators_enabled;
+        multisig.max_transfer_lamports = max_transfer_lamports;
+        Ok(())
+    }
+
+    // Sets the fee charged to proposers for each new transaction. Only
+    // callable by the multisig itself.
+    pub fn set_proposal_fee(
+        ctx: Context<Auth>,
+        proposal_fee_lamports: u64,
+        fee_treasury: Option<Pubkey>,
+        refund_fee_on_execute: bool,
+    ) -> Result<()> {
+        // Refunds are paid by the treasury, so it must be the multisig signer.
+        if refund_fee_on_execute && fee_treasury != Some(*ctx.accounts.multisig_signer.key) {
+            return Err(ErrorCode::InvalidFeeTreasury.into());
+        }
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.proposal_fee_lamports = proposal_fee_lamports;
+        multisig.fee_treasury = fee_treasury;
+        multisig.refund_fee_on_execute = refund_fee_on_execute;
+        Ok(())
+    }
+
+    // Sets the fee charged, per instruction, to the account executing a
+    // transaction. Only callable by the multisig itself.
+    pub fn set_execution_fee(
+        ctx: Context<Auth>,
+        execution_compute_fee_lamports_per_ix: u64,
+    ) -> Result<()> {
+        ctx.accounts.multisig.execution_compute_fee_lamports_per_ix =
+            execution_compute_fee_lamports_per_ix;
+        Ok(())
+    }
+
+    // Sets the owners that must approve every transaction, on top of the
+    // threshold being reached. Only callable by the multisig itself.
+    pub fn set_required_approvers(
+        ctx: Context<Auth>,
+        required_approvers: Vec<Pubkey>,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        for r in required_approvers.iter() {
+            multisig.check_permission(r, PERMISSION_APPROVE)?;
+        }
+        multisig.required_approvers = required_approvers;
+        Ok(())
+    }
+
+    // Sets the groups of owners each transaction needs a quorum of approvals
+    // from, on top of the threshold being reached. Only callable by the
+    // multisig itself.
+    pub fn set_approval_groups(
+        ctx: Context<Auth>,
+        approval_groups: Vec<ApprovalGroup>,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.approval_groups = approval_groups;
+        multisig.check_approval_groups()?;
+        Ok(())
+    }
+
+    // Sets the key allowed to veto any pending transaction, or removes it if
+    // `None`. Only callable by the multisig itself.
+    pub fn set_veto_authority(ctx: Context<Auth>, veto_authority: Option<Pubkey>) -> Result<()> {
+        ctx.accounts.multisig.veto_authority = veto_authority;
+        Ok(())
+    }
+
+    // Sets who may freeze the multisig: the guardian key, if any, and any
+    // `freeze_threshold` owners, or no owners if zero. Only callable by the
+    // multisig itself.
+    pub fn set_freeze_config(
+        ctx: Context<Auth>,
+        freeze_guardian: Option<Pubkey>,
+        freeze_threshold: u64,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        if freeze_threshold > multisig.owners.len() as u64 {
+            return Err(ErrorCode::InvalidThreshold.into());
+        }
+        multisig.freeze_guardian = freeze_guardian;
+        multisig.freeze_threshold = freeze_threshold;
+        Ok(())
+    }
+
+    // Sets the guardians allowed to replace the owners once they have been
+    // inactive for `recovery_inactivity_period` seconds, how many of them
+    // must sign, and how many seconds a recovery takes to complete. The
+    // period and delay may not be below `MIN_RECOVERY_INACTIVITY_PERIOD` and
+    // `MIN_RECOVERY_DELAY`, leaving the owners time to notice. No guardians
+    // disables recovery. Cancels any pending recovery. Only callable
+    // by the multisig itself.
+    pub fn set_recovery_config(
+        ctx: Context<Auth>,
+        guardians: Vec<Pubkey>,
+        guardian_threshold: u64,
+        recovery_inactivity_period: i64,
+        recovery_delay: i64,
+    ) -> Result<()> {
+        if !guardians.is_empty() {
+            check_owners(&guardians)?;
+            if guardian_threshold == 0 || guardian_threshold > guardians.len() as u64 {
+                return Err(ErrorCode::InvalidRecoveryConfig.into());
+            }
+            if recovery_inactivity_period < MIN_RECOVERY_INACTIVITY_PERIOD
+                || recovery_delay < MIN_RECOVERY_DELAY
+            {
+                return Err(ErrorCode::InvalidRecoveryConfig.into());
+            }
+        }
+        if recovery_inactivity_period < 0 || recovery_delay < 0 {
+            return Err(ErrorCode::InvalidRecoveryConfig.into());
+        }
+        let multisig = &mut ctx.accounts.multisig;
+        multisig.guardians = guardians;
+        multisig.guardian_threshold = guardian_threshold;
+        multisig.recovery_inactivity_period = recovery_inactivity_period;
+        multisig.recovery_delay = recovery_delay;
+        multisig.pending_recovery = None;
+        Ok(())
+    }
+
+    // Sets whether transactions must execute in the order they were proposed.
+    // While set, executing a transaction makes every transaction proposed
+    // before it stale, i.e. unexecutable. Only callable by the multisig itself.
+    pub fn set_sequential_execution(ctx: Context<Auth>, sequential_execution: bool) -> Result<()> {
+        ctx.accounts.multisig.sequential_execution = sequential_execution;
+        Ok(())
+    }
+
+    // Sets the lamports the multisig signer pays whoever executes a
+    // transaction. Only callable by the multisig itself.
+    pub fn set_executor_tip(ctx: Context<Auth>, executor_tip_lamports: u64) -> Result<()> {
+        ctx.accounts.multisig.executor_tip_lamports = executor_tip_lamports;
+        Ok(())
+    }
+
+    // Overrides the number of instructions the `VALIDATOR_INSTRUCTION_COUNT`
+    // policy allows per transaction, or restores the program default if zero.
+    // Only callable by the multisig itself.
+    pub fn set_max_instructions(ctx: Context<Auth>, max_instructions_global: u8) -> Result<()> {
+        ctx.accounts.multisig.max_instructions_global = max_instructions_global;
+        Ok(())
+    }
+
+    // Sets the delay between a transaction reaching the threshold and its
+    // earliest execution. Only callable by the multisig itself.
+    pub fn set_timelock(ctx: Context<Auth>, timelock_seconds: i64) -> Result<()> {
+        if timelock_seconds < 0 {
+            return Err(ErrorCode::InvalidTimelock.into());
+        }
+        ctx.accounts.multisig.timelock_seconds = timelock_seconds;
+        Ok(())
+    }
+
+    // Sets where the rent of closed transactions goes, or the proposer of each
+    // transaction if `None`. Only callable by the multisig itself.
+    pub fn set_close_destination(
+        ctx: Context<Auth>,
+        close_destination: Option<Pubkey>,
+    ) -> Result<()> {
+        ctx.accounts.multisig.close_destination = close_destination;
+        Ok(())
+    }
+
+    // Lets the given owner spend up to `amount` of the given mint, or of the
+    // multisig signer's lamports if `None`, every `period` seconds without a
+    // proposal. Replaces any previous limit, starting a new period. Only
+    // callable by the multisig itself.
+    pub fn set_spending_limit(
+        ctx: Context<Auth>,
+        owner: Pubkey,
+        mint: Option<Pubkey>,
+        amount: u64,
+        period: i64,
+    ) -> Result<()> {
+        let multisig = &mut ctx.accounts.multisig;
+        if !multisig.owners.contains(&owner) {
+            return Err(ErrorCode::InvalidOwner.into());
+        }
+        if period <= 0 {
+            return Err(ErrorCode::InvalidSpendingLimit.into());
+        }
+        multisig.spending_limit = Some(SpendingLimit {
+            owner,
+            mint,
+            amount,
+            period,
+            spent: 0,
+            period_start: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+
+    // Removes the spending limit. Only callable by the multisig itself.
+    pub fn remove_spending_limit(ctx: Context<Auth>) -> Result<()> {
+        ctx.accounts.multisig.spending_limit = None;
+        Ok(())
+    }
+
+    // Commits to the hash of the program bits of the buffer the multisig
+    // program will be upgraded with. Only callable by the multisig itself.
+    pub fn commit_upgrade_buffer(ctx: Context<Auth>, buffer_hash: [u8; 32]) -> Result<()> {
+        ctx.accounts.multisig.upgrade_buffer_hash = Some(buffer_hash);
+        Ok(())
+    }
+
+    // Upgrades the multisig program from the given buffer, whose contents must
+    // match the committed hash. Only callable by the multisig itself, which
+    // must be the program's upgrade authority.
+    pub fn upgrade_self(ctx: Context<UpgradeSelf>, buffer: Pubkey) -> Result<()> {
+        if &buffer != ctx.accounts.buffer.key {
+            return Err(ErrorCode::InvalidUpgradeBuffer.into());
+        }
+        let offset = UpgradeableLoaderState::buffer_data_offset()
+            .map_err(|_| ErrorCode::InvalidUpgradeBuffer)?;
+        let buffer_hash = {
+            let data = ctx.accounts.buffer.try_borrow_data()?;
+            if data.len() < offset {
+                return Err(ErrorCode::InvalidUpgradeBuffer.into());
+            }
+            hash::hash(&data[offset..]).to_bytes()
+        };
+        if ctx.accounts.multisig.upgrade_buffer_hash != Some(buffer_hash) {
+            return Err(ErrorCode::UpgradeHashMismatch.into());
+        }
+        // Fail with a clear error rather than inside the loader if the
+        // multisig doesn't hold the upgrade authority.
+        check_upgrade_authority(&ctx.accounts.program_data, ctx.accounts.multisig_signer.key)?;
+
+        let ix = bpf_loader_upgradeable::upgrade(
+            ctx.program_id,
+            &buffer,
+            ctx.accounts.multisig_signer.key,
+            ctx.accounts.spill.key,
+        );
+        let seeds = &[
+            ctx.accounts.multisig.to_account_info().key.as_ref(),
+            &[ctx.accounts.multisig.nonce],
+        ];
+        let signer = &[&seeds[..]];
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.program_data.clone(),
+                ctx.accounts.program.clone(),
+                ctx.accounts.buffer.clone(),
+                ctx.accounts.spill.clone(),
+                ctx.accounts.rent.to_account_info(),
+                ctx.accounts.clock.to_account_info(),
+                ctx.accounts.multisig_signer.clone(),
+                ctx.accounts.loader.clone(),
+            ],
+            signer,
+        )?;
+
+        // The commitment is single use.
+        ctx.accounts.multisig.upgrade_buffer_hash = None;
+
+        Ok(())
+    }
+
+    // Logs the weight the given owner's approval carries, and the most all
+    // owners' approvals can reach, as a `VotingPower` event. Owners can't
+    // delegate their votes, so it is the owner's own weight, or nothing
+    // without `PERMISSION_APPROVE`.
+    pub fn compute_effective_voting_power(
+        ctx: Context<ComputeVotingPower>,
+        owner: Pubkey,
+    ) -> Result<()> {
+        let multisig = &ctx.accounts.multisig;
+        if !multisig.owners.contains(&owner) {
+            return Err(ErrorCode::InvalidOwner.into());
+        }
+        emit!(VotingPower {
+            multisig: *multisig.to_account_info().key,
+            owner,
+            weight: multisig.approval_weight_of(&owner),
+            total_weight: multisig.total_weight()?,
+        });
+        Ok(())
+    }
+
+    // Logs the multisig signer's address, bump and balance as a `SignerInfo`
+    // event, so monitoring tools can fetch them with a single simulation.
+    pub fn get_signer_info(ctx: Context<GetSignerInfo>) -> Result<()> {
+        let lamports = ctx.accounts.multisig_signer.lamports();
+        emit!(SignerInfo {
+            signer_pda: *ctx.accounts.multisig_signer.key,
+            bump: ctx.accounts.multisig.nonce,
+            lamports,
+            is_rent_exempt: Rent::get()?.is_exempt(lamports, 0),
+        });
+        Ok(())
+    }
+
+    // Creates the program wide pause switch, controlled by the given authority.
+    // Only callable by the upgrade authority of this program.
+    pub fn init_global_pause(ctx: Context<InitGlobalPause>, authority: Pubkey) -> Result<()> {
+        check_program_data(ctx.program_id, &ctx.accounts.program_data)?;
+        check_upgrade_authority(
+            &ctx.accounts.program_data,
+            ctx.accounts.upgrade_authority.key,
+        )?;
+        let (address, bump) = pda::global_pause(ctx.program_id);
+        if &address != ctx.accounts.global_pause.key {
+            return Err(ErrorCode::InvalidGlobalPause.into());
+        }
+        let global_pause = GlobalPause {
+            authority,
+            paused: false,
+        };
+        // Discriminator, authority and paused flag.
+        let space = 8 + 32 + 1;
+        let ix = system_instruction::create_account(
+            ctx.accounts.upgrade_authority.key,
+            &address,
+            Rent::get()?.minimum_balance(space),
+            space as u64,
+            ctx.program_id,
+        );
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                ctx.accounts.upgrade_authority.clone(),
+                ctx.accounts.global_pause.clone(),
+                ctx.accounts.system_program.clone(),
+            ],
+            &[&[b"global_pause", &[bump]]],
+        )?;
+        let mut data = ctx.accounts.global_pause.try_borrow_mut_data()?;
+        global_pause.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    // Hands control of the program wide pause switch to a new authority.
+    pub fn set_global_pause_authority(
+        ctx: Context<GlobalPauseAuth>,
+        new_authority: Pubkey,
+    ) -> Result<()> {
+        ctx.accounts.global_pause.authority = new_authority;
+        Ok(())
+    }
+
+    // Pauses every multisig of this program, when built with the
+    // `check_global_pause` feature.
+    pub fn activate_global_pause(ctx: Context<GlobalPauseAuth>) -> Result<()> {
+        ctx.accounts.global_pause.paused = true;
+        Ok(())
+    }
+
+    // Lifts a program wide pause.
+    pub fn deactivate_global_pause(ctx: Context<GlobalPauseAuth>) -> Result<()> {
+        ctx.accounts.global_pause.paused = false;
+        Ok(())
+    }
+
+    // Does nothing but check the given multisig account is valid, logging a
+    // heartbeat so monitoring tools can verify the program is live.
+    pub fn noop(ctx: Context<Noop>) -> Result<()> {
+        emit!(Heartbeat {
+            multisig: *ctx.accounts.multisig.to_account_info().key,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+        Ok(())
+    }
+}
+
+// Fails if the program wide pause is active. The `GlobalPause` account must be
+// given as a remaining account. Only enforced with the `check_global_pause`
+// feature.
+//
+// The pause covers everything that proposes, approves or executes anything,
+// or changes who controls a multisig: creating multisigs, all proposals,
+// drafts and instruction buffers, approvals, executions (and with them the
+// `Auth` setters), spending limits, owner acceptance and key rotation,
+// recoveries and unfreezing. The exceptions only withdraw, cancel, restrict,
+// clean up or read:
+// - `revoke_approval`, `reject`, `veto`, `freeze` and `cancel_recovery`,
+// - `delete_transaction` and `close_transaction`,
+// - `add_instruction`, `write_instruction_buffer` and the
+//   `set_transaction_*` edits, whose results can't be proposed or executed
+//   while paused anyway,
+// - `validate_accounts_present`, `compute_effective_voting_power`,
+//   `get_signer_info` and `noop`,
+// - `init_transaction_history` and `init_changelog`, which only record,
+// - the global pause's own instructions.
+#[cfg(feature = "check_global_pause")]
+fn check_global_pause(program_id: &Pubkey, remaining_accounts: &[AccountInfo]) -> Result<()> {
+    let (address, _) = pda::global_pause(program_id);
+    let info = TransactionAccountMap::new(remaining_accounts)
+        .get(&address)
+        .ok_or(ErrorCode::MissingAccount)?;
+    if info.owner != program_id {
+        return Err(ErrorCode::MissingAccount.into());
+    }
+    let global_pause = GlobalPause::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    if global_pause.paused {
+        return Err(ErrorCode::GloballyPaused.into());
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "check_global_pause"))]
+fn check_global_pause(_program_id: &Pubkey, _remaining_accounts: &[AccountInfo]) -> Result<()> {
+    Ok(())
+}
+
+// Appends the given entry to the multisig's `TransactionHistory`, if given
+// among the accounts, overwriting the oldest once it holds
+// `MAX_HISTORY_ENTRIES`.
+fn record_history(
+    program_id: &Pubkey,
+    multisig: &ProgramAccount<Multisig>,
+    accounts: &TransactionAccountMap,
+    entry: HistoryEntry,
+) -> Result<()> {
+    let (address, _) = pda::history(multisig.to_account_info().key, program_id);
+    let info = match accounts.get(&address) {
+        Some(info) if info.owner == program_id => info,
+        _ => return Ok(()),
+    };
+    let mut history = TransactionHistory::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let next = history.next as usize;
+    if next < history.entries.len() {
+        history.entries[next] = entry;
+    } else {
+        history.entries.push(entry);
+    }
+    history.next = ((next + 1) % MAX_HISTORY_ENTRIES) as u8;
+    history.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+// Writes an entry for the given `CHANGE_*` action to the multisig's
+// `MultisigChangelog`, if given as a remaining account, over the oldest one.
+fn record_change(
+    program_id: &Pubkey,
+    multisig: &Pubkey,
+    remaining_accounts: &[AccountInfo],
+    action: u8,
+    actor: &Pubkey,
+    transaction: Option<Pubkey>,
+) -> Result<()> {
+    let (address, _) = pda::changelog(multisig, program_id);
+    let info = match TransactionAccountMap::new(remaining_accounts).get(&address) {
+        Some(info) if info.owner == program_id => info,
+        _ => return Ok(()),
+    };
+    let mut changelog = MultisigChangelog::try_deserialize(&mut &info.try_borrow_data()?[..])?;
+    let head = changelog.head as usize % CHANGELOG_LEN;
+    changelog.entries[head] = ChangelogEntry {
+        action,
+        actor: *actor,
+        timestamp: Clock::get()?.unix_timestamp,
+        transaction,
+    };
+    changelog.head = ((head + 1) % CHANGELOG_LEN) as u8;
+    changelog.try_serialize(&mut &mut info.try_borrow_mut_data()?[..])?;
+    Ok(())
+}
+
+// The upgrade authority recorded in the given upgradeable loader program data
+// account, if any.
+fn program_upgrade_authority(program_data: &AccountInfo) -> Result<Option<Pubkey>> {
+    if program_data.owner != &bpf_loader_upgradeable::ID {
+        return Err(ErrorCode::InvalidProgramData.into());
+    }
+    // Bincode encoded `UpgradeableLoaderState::ProgramData { slot,
+    // upgrade_authority_address }`.
+    let data = program_data.try_borrow_data()?;
+    if data.len() < 45 || data[..4] != 3u32.to_le_bytes() {
+        return Err(ErrorCode::InvalidProgramData.into());
+    }
+    match data[12] {
+        0 => Ok(None),
+        _ => {
+            let mut authority = [0u8; 32];
+            authority.copy_from_slice(&data[13..45]);
+            Ok(Some(Pubkey::new_from_array(authority)))
+        }
+    }
+}
+
+// Whether the instruction is an upgradeable loader `SetAuthority`, handing off
+// the upgrade authority of a program or the authority of a buffer.
+fn is_upgrade_authority_transfer(ix: &Instruction) -> bool {
+    // Bincode encoded `UpgradeableLoaderInstruction::SetAuthority`.
+    ix.program_id == bpf_loader_upgradeable::ID && ix.data.starts_with(&4u32.to_le_bytes())
+}
+
+// The authority recorded in the given upgradeable loader buffer account, if
+// any.
+fn buffer_authority(buffer: &AccountInfo) -> Result<Option<Pubkey>> {
+    if buffer.owner != &bpf_loader_upgradeable::ID {
+        return Err(ErrorCode::InvalidUpgradeBuffer.into());
+    }
+    // Bincode encoded `UpgradeableLoaderState::Buffer { authority_address }`.
+    let data = buffer.try_borrow_data()?;
+    if data.len() < 37 || data[..4] != 1u32.to_le_bytes() {
+        return Err(ErrorCode::InvalidUpgradeBuffer.into());
+    }
+    match data[4] {
+        0 => Ok(None),
+        _ => {
+            let mut authority = [0u8; 32];
+            authority.copy_from_slice(&data[5..37]);
+            Ok(Some(Pubkey::new_from_array(authority)))
+        }
+    }
+}
+
+// Fails unless the given account is the program data account of the given
+// upgradeable program.
+fn check_program_data(program: &Pubkey, program_data: &AccountInfo) -> Result<()> {
+    let (address, _) =
+        Pubkey::find_program_address(&[program.as_ref()], &bpf_loader_upgradeable::ID);
+    if &address != program_data.key {
+        return Err(ErrorCode::InvalidProgramData.into());
+    }
+    Ok(())
+}
+
+// Fails unless `expected` is the upgrade authority recorded in the given
+// program data account, logging the actual authority otherwise.
+fn check_upgrade_authority(program_data: &AccountInfo, expected: &Pubkey) -> Result<()> {
+    let current = program_upgrade_authority(program_data)?;
+    if current != Some(*expected) {
+        msg!(
+            "Upgrade authority is {:?}, expected {}",
+            current.map(|c| c.to_string()),
+            expected
+        );
+        return Err(ErrorCode::InvalidUpgradeAuthority.into());
+    }
+    Ok(())
+}
+
+// Checks an owner list is non empty, free of duplicates and no longer than
+// `MAX_OWNERS`.
+fn check_owners(owners: &[Pubkey]) -> Result<()> {
+    if owners.is_empty() {
+        return Err(ErrorCode::InvalidOwnersLen.into());
+    }
+    if owners.len() > MAX_OWNERS {
+        return Err(ErrorCode::TooManyOwners.into());
+    }
+    for (i, owner) in owners.iter().enumerate() {
+        if owners[..i].contains(owner) {
+            return Err(ErrorCode::DuplicateOwner.into());
+        }
+    }
+    Ok(())
+}
+
+// Checks the owners and threshold of a new multisig, whose owners all have a
+// weight of one.
+fn check_new_multisig(owners: &[Pubkey], threshold: u64) -> Result<()> {
+    check_owners(owners)?;
+    if threshold == 0 || threshold > owners.len() as u64 {
+        return Err(ErrorCode::InvalidThreshold.into());
+    }
+    Ok(())
+}
+
+// Checks the nonce of a new multisig is the canonical bump of its signer, so
+// every multisig has exactly one signer address.
+fn check_nonce(multisig: &Pubkey, nonce: u8, program_id: &Pubkey) -> Result<()> {
+    let (_, bump) = pda::multisig_signer(multisig, program_id);
+    if nonce != bump {
+        msg!("Nonce {} is not the canonical bump {}", nonce, bump);
+        return Err(ErrorCode::InvalidNonce.into());
+    }
+    Ok(())
+}
+
+// Zips the parallel program id, account and data vectors of a proposal into
+// instructions.
+fn instructions(
+    pid: Vec<Pubkey>,
+    accs: Vec<Vec<TransactionAccount>>,
+    data: Vec<Vec<u8>>,
+) -> Result<Vec<Instruction>> {
+    if pid.len() != accs.len() || pid.len() != data.len() {
+        return Err(ErrorCode::ParamLength.into());
+    }
+    Ok(pid
+        .into_iter()
+        .zip(accs.into_iter().zip(data))
+        .map(|(program_id, (accs, data))| Instruction {
+            program_id,
+            accounts: accs.into_iter().map(Into::into).collect(),
+            data,
+        })
+        .collect())
+}
+
+// Proposes the single instruction built for the multisig signer, for the
+// typed `propose_*` instructions.
+fn propose_signed_by_multisig<'info>(
+    ctx: Context<'_, '_, '_, 'info, CreateTransaction<'info>>,
+    expires_at: Option<i64>,
+    build: impl FnOnce(&Pubkey) -> Instruction,
+) -> Result<()> {
+    let signer = ctx
+        .accounts
+        .multisig
+        .signer(ctx.accounts.multisig.to_account_info().key, ctx.program_id)?;
+    let ix = build(&signer);
+    ctx.accounts.transaction.expires_at = expires_at;
+    propose(
+        ctx.program_id,
+        &mut ctx.accounts.multisig,
+        &mut ctx.accounts.transaction,
+        &ctx.accounts.proposer,
+        ctx.remaining_accounts,
+        vec![ix],
+    )
+}
+
+// Checks, in debug builds only, that every approval of the transaction is of a
+// current owner. Any owner change bumps `owner_set_seqno`, which pending
+// transactions must match, so this should always hold.
+fn debug_assert_approvals_consistent(tx: &Transaction, multisig: &Multisig) {
+    debug_assert!(
+        tx.approvals
+            .iter()
+            .all(|a| multisig.owners.contains(&a.owner)),
+        "approvals out of sync with owners"
+    );
+}
+
+// Records an owner's approval of a transaction. See `multisig::approve`.
+fn approve_as(
+    multisig: &ProgramAccount<Multisig>,
+    tx: &mut ProgramAccount<Transaction>,
+    owner: &Pubkey,
+) -> Result<()> {
+    debug_assert_approvals_consistent(tx, multisig);
+    multisig.check_not_frozen()?;
+    tx.check_pending()?;
+    let now = Clock::get()?.unix_timestamp;
+    if tx.is_expired(now) {
+        return Err(ErrorCode::TransactionExpired.into());
+    }
+    multisig.check_permission(owner, PERMISSION_APPROVE)?;
+
+    if tx.approvals.iter().any(|a| &a.owner == owner) {
+        return Err(ErrorCode::AlreadyApproved.into());
+    }
+    tx.approve(owner, now);
+    tx.update_threshold_reached(multisig, now)?;
+
+    emit!(TransactionApproved {
+        multisig: tx.multisig,
+        transaction: *tx.to_account_info().key,
+        index: tx.index,
+        owner: *owner,
+    });
+    Ok(())
+}
+
+// The SHA-256 hash committed to by `create_hashed_transaction`: of the Borsh
+// encoded `pid`, `accs` and `data` arguments `reveal_transaction` is given.
+fn instructions_hash(
+    pid: &[Pubkey],
+    accs: &[Vec<TransactionAccount>],
+    data: &[Vec<u8>],
+) -> Result<[u8; 32]> {
+    let encode = |e: std::io::Error| ProgramError::BorshIoError(e.to_string());
+    let pid = pid.try_to_vec().map_err(encode)?;
+    let accs = accs.try_to_vec().map_err(encode)?;
+    let data = data.try_to_vec().map_err(encode)?;
+    Ok(hash::hashv(&[&pid, &accs, &data]).to_bytes())
+}
+
+// Executes the next `count` instructions of the given transaction, if
+// threshold owners have signed it, and marks it executed once all of them are.
+fn execute_instructions<'info>(
+    program_id: &Pubkey,
+    multisig: &mut ProgramAccount<'info, Multisig>,
+    multisig_signer: &AccountInfo<'info>,
+    transaction: &mut ProgramAccount<'info, Transaction>,
+    executor: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    count: usize,
+) -> Result<()> {
+    debug_assert_approvals_consistent(transaction, multisig);
+    check_global_pause(program_id, remaining_accounts)?;
+    multisig.check_not_frozen()?;
+    // To tell whether the execution changed the owners.
+    let owner_set_seqno = multisig.owner_set_seqno;
+    if multisig.restrict_execution {
+        multisig.check_permission(executor.key, PERMISSION_EXECUTE)?;
+    }
+
+    // Has this been executed or cancelled already?
+    transaction.check_pending()?;
+    // Recurring transactions only go stale before their first run.
+    if multisig.sequential_execution
+        && transaction.execution_count == 0
+        && transaction.index < multisig.next_execution_index
+    {
+        return Err(ErrorCode::StaleTransaction.into());
+    }
+    if transaction.is_expired(Clock::get()?.unix_timestamp) {
+        return Err(ErrorCode::TransactionExpired.into());
+    }
+    // Are we inside the transaction's execution window?
+    let now = Clock::get()?.unix_timestamp;
+    if matches!(transaction.not_before, Some(t) if now < t)
+        || matches!(transaction.not_after, Some(t) if now >= t)
+    {
+        return Err(ErrorCode::OutsideExecutionWindow.into());
+    }
+    // Has a recurring transaction cooled down since its last run?
+    if let Some(last) = transaction.last_executed_at {
+        let elapsed = Clock::get()?
+            .unix_timestamp
+            .checked_sub(last)
+            .ok_or(ErrorCode::Overflow)?;
+        if transaction.next_instruction == 0 && elapsed < transaction.min_execution_interval {
+            return Err(ErrorCode::CooldownNotElapsed.into());
+        }
+    }
+
+    // Do we have enough signers.
+    let tx = &transaction;
+    if tx.approval_weight(multisig)? < multisig.threshold {
+        return Err(ErrorCode::NotEnoughSigners.into());
+    }
+    if !tx.has_required_approvals(multisig) {
+        return Err(ErrorCode::MissingRequiredApproval.into());
+    }
+    if !tx.has_group_approvals(multisig) {
+        return Err(ErrorCode::GroupQuorumNotReached.into());
+    }
+
+    let accounts = TransactionAccountMap::new(remaining_accounts);
+    if let Some(depends_on) = transaction.depends_on {
+        check_dependency_executed(program_id, transaction, &accounts, &depends_on)?;
+    }
+
+    // Execute the next instructions signed by the multisig.
+    let mut ixs = transaction.instructions(&accounts)?;
+    if transaction.sort_instructions_by_program {
+        // Stable, so instructions for the same program keep their order.
+        ixs.sort_by_key(|ix| ix.program_id);
+    }
+    let total = ixs.len();
+    let next = transaction.next_instruction as usize;
+    let config_ixs = config_instructions(program_id, multisig, &transaction.config_actions)?;
+    let policy_ixs = [&ixs[..], &config_ixs[..]].concat();
+    let threshold = multisig.threshold_for(&policy_ixs);
+    if tx.approval_weight(multisig)? < threshold {
+        return Err(ErrorCode::NotEnoughSigners.into());
+    }
+
+    // Has the timelock passed since the approvals reached everything the
+    // transaction needs? `threshold_reached_at` only tracks the multisig's
+    // threshold, so a higher tier may have been reached later. Handing off an
+    // upgrade authority can't be undone, so it may need a longer timelock.
+    let mut timelock = multisig.timelock_seconds;
+    if ixs.iter().any(is_upgrade_authority_transfer) {
+        timelock = timelock.max(multisig.authority_transfer_timelock);
+    }
+    if timelock > 0 {
+        let reached_at = tx
+            .threshold_reached_at
+            .ok_or(ErrorCode::TimelockNotExpired)?
+            .max(
+                tx.weight_reached_at(multisig, threshold)?
+                    .ok_or(ErrorCode::TimelockNotExpired)?,
+            );
+        let elapsed = Clock::get()?
+            .unix_timestamp
+            .checked_sub(reached_at)
+            .ok_or(ErrorCode::Overflow)?;
+        if elapsed < timelock {
+            return Err(ErrorCode::TimelockNotExpired.into());
+        }
+    }
+    let ixs: Vec<Instruction> = ixs.into_iter().skip(next).take(count).collect();
+    let executed = next + ixs.len();
+    // Deny rules added after the proposal apply too.
+    for ix in ixs.iter().chain(config_ixs.iter()) {
+        multisig.check_not_denied(&ix.program_id, &ix.data)?;
+    }
+
+    // Charge the executor for the instructions being run, if the
+    // multisig has a treasury. The treasury and the system program are
+    // given as remaining accounts.
+    if let Some(treasury) = multisig.fee_treasury {
+        let fee = (ixs.len() as u64)
+            .checked_mul(multisig.execution_compute_fee_lamports_per_ix)
+            .ok_or(ErrorCode::Overflow)?;
+        if fee > 0 {
+            if executor.lamports() < fee {
+                return Err(ErrorCode::InsufficientComputeFee.into());
+            }
+            let ix = system_instruction::transfer(executor.key, &treasury, fee);
+            solana_program::program::invoke(
+                &ix,
+                &[
+                    executor.clone(),
+                    accounts
+                        .get(&treasury)
+                        .ok_or(ErrorCode::MissingAccount)?
+                        .clone(),
+                    accounts
+                        .get(&system_program::ID)
+                        .ok_or(ErrorCode::MissingAccount)?
+                        .clone(),
+                ],
+            )?;
+        }
+    }
+
+    let tx = &transaction;
+    execute(
+        multisig,
+        program_id,
+        tx.vault_index,
+        Some((tx.to_account_info().key, tx.ephemeral_signers)),
+        &accounts,
+        ixs,
+        next,
+    )?;
+    // Reload before anything is written back, including when only some of the
+    // instructions ran.
+    record_execution(multisig)?;
+
+    transaction.next_instruction = executed as u8;
+    if executed < total {
+        return Ok(());
+    }
+
+    // Burn the transaction to ensure one time use, unless it may run again.
+    let now = Clock::get()?.unix_timestamp;
+    transaction.execution_count = transaction
+        .execution_count
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+    transaction.last_executed_at = Some(now);
+    let finished = transaction.execution_count >= transaction.max_executions.max(1);
+    if finished {
+        transaction.status = TransactionStatus::Executed;
+        transaction.finalized_at = Some(now);
+    } else {
+        transaction.next_instruction = 0;
+    }
+    transaction.executor = Some(*executor.key);
+    for action in transaction.config_actions.clone() {
+        apply_config_action(multisig, action)?;
+    }
+    if transaction.index >= multisig.next_execution_index {
+        multisig.next_execution_index = transaction.index + 1;
+    }
+    let tx = &transaction;
+    emit!(TransactionExecuted {
+        multisig: tx.multisig,
+        transaction: *tx.to_account_info().key,
+        index: tx.index,
+        executor: *executor.key,
+    });
+    let entry = HistoryEntry {
+        transaction_hash: tx.contents_hash()?,
+        executed_at: now,
+        executor: *executor.key,
+        instruction_count: total as u8,
+    };
+    record_history(program_id, multisig, &accounts, entry)?;
+    record_change(
+        program_id,
+        &tx.multisig,
+        remaining_accounts,
+        CHANGE_EXECUTED,
+        executor.key,
+        Some(*tx.to_account_info().key),
+    )?;
+    if multisig.owner_set_seqno != owner_set_seqno {
+        record_change(
+            program_id,
+            &tx.multisig,
+            remaining_accounts,
+            CHANGE_OWNERS_CHANGED,
+            executor.key,
+            Some(*tx.to_account_info().key),
+        )?;
+    }
+
+    // Refund the proposal fee from the multisig signer, which is the only
+    // treasury the program can sign for.
+    let tx = &transaction;
+    if finished && multisig.refund_fee_on_execute && tx.fee_paid > 0 {
+        let ix = system_instruction::transfer(multisig_signer.key, &tx.proposer, tx.fee_paid);
+        let seeds = &[multisig.to_account_info().key.as_ref(), &[multisig.nonce]];
+        let signer = &[&seeds[..]];
+        solana_program::program::invoke_signed(
+            &ix,
+            &[
+                multisig_signer.clone(),
+                accounts
+                    .get(&tx.proposer)
+                    .ok_or(ErrorCode::MissingAccount)?
+                    .clone(),
+                accounts
+                    .get(&system_program::ID)
+                    .ok_or(ErrorCode::MissingAccount)?
+                    .clone(),
+            ],
+            signer,
+        )?;
+    }
+
+    // Tip the executor, so anyone can crank approved transactions without
+    // being out of pocket. A multisig signer too poor to pay doesn't block
+    // the execution.
+    let tip = multisig.executor_tip_lamports;
+    if tip > 0 {
+        if multisig_signer.lamports() < tip {
+            msg!("Multisig signer can't afford the executor tip");
+        } else {
+            let ix = system_instruction::transfer(multisig_signer.key, executor.key, tip);
+            let seeds = &[multisig.to_account_info().key.as_ref(), &[multisig.nonce]];
+            let signer = &[&seeds[..]];
+            solana_program::program::invoke_signed(
+                &ix,
+                &[
+                    multisig_signer.clone(),
+                    executor.clone(),
+                    accounts
+                        .get(&system_program::ID)
+                        .ok_or(ErrorCode::MissingAccount)?
+                        .clone(),
+                ],
+                signer,
+            )?;
+        }
+    }
+
+    Ok(())
+}
+
+// Checks the dependency of the given transaction, given among the accounts, is
+// an executed transaction of the same multisig.
+fn check_dependency_executed(
+    program_id: &Pubkey,
+    transaction: &Transaction,
+    accounts: &TransactionAccountMap,
+    depends_on: &Pubkey,
+) -> Result<()> {
+    let info = accounts.get(depends_on).ok_or(ErrorCode::MissingAccount)?;
+    if info.owner != program_id {
+        return Err(ErrorCode::InvalidDependency.into());
+    }
+    let dependency: ProgramAccount<Transaction> = ProgramAccount::try_from(info)?;
+    if dependency.multisig != transaction.multisig {
+        return Err(ErrorCode::InvalidDependency.into());
+    }
+    if dependency.status != TransactionStatus::Executed {
+        return Err(ErrorCode::DependencyNotExecuted.into());
+    }
+    Ok(())
+}
+
+// Invokes the given instructions, signed by the multisig's vault at the given
+// index and, if given, by the ephemeral signers of the given transaction. The
+// first instruction is logged as `first_index` of its transaction.
+fn execute(
+    multisig: &ProgramAccount<Multisig>,
+    program_id: &Pubkey,
+    vault_index: u8,
+    ephemeral_signers: Option<(&Pubkey, u8)>,
+    accounts: &TransactionAccountMap,
+    mut ixs: Vec<Instruction>,
+    first_index: usize,
+) -> Result<()> {
+    let multisig_key = multisig.to_account_info().key;
+    let (vault, bump) = if vault_index == 0 {
+        (multisig.signer(multisig_key, program_id)?, multisig.nonce)
+    } else {
+        pda::vault(multisig_key, vault_index, program_id)
+    };
+    // Keys, indices and bumps of the ephemeral signers.
+    let ephemeral: Vec<(Pubkey, [u8; 1], [u8; 1])> = match ephemeral_signers {
+        Some((transaction, count)) => (0..count)
+            .map(|i| {
+                let (key, bump) = pda::ephemeral_signer(transaction, i, program_id);
+                (key, [i], [bump])
+            })
+            .collect(),
+        None => Vec::new(),
+    };
+    for ix in ixs.iter_mut() {
+        for acc in ix.accounts.iter_mut() {
+            if acc.pubkey == vault || ephemeral.iter().any(|(key, _, _)| key == &acc.pubkey) {
+                acc.is_signer = true;
+            }
+        }
+    }
+
+    let index = [vault_index];
+    let bump = [bump];
+    let seeds: &[&[u8]] = if vault_index == 0 {
+        &[multisig_key.as_ref(), &bump]
+    } else {
+        &[multisig_key.as_ref(), &index, &bump]
+    };
+    let mut ephemeral_seeds: Vec<[&[u8]; 4]> = Vec::new();
+    if let Some((transaction, _)) = ephemeral_signers {
+        for (_, index, bump) in ephemeral.iter() {
+            ephemeral_seeds.push([
+                pda::EPHEMERAL_SIGNER_SEED,
+                transaction.as_ref(),
+                index,
+                bump,
+            ]);
+        }
+    }
+    let mut signer: Vec<&[&[u8]]> = vec![seeds];
+    signer.extend(ephemeral_seeds.iter().map(|s| &s[..]));
+
+    // Check every instruction before invoking any, so a substituted account
+    // can't make a prefix of the transaction execute.
+    let mut program_signers: Vec<Pubkey> = ephemeral.iter().map(|(key, _, _)| *key).collect();
+    program_signers.push(vault);
+    for ix in ixs.iter() {
+        accounts.check_instruction_accounts(ix, &program_signers)?;
+    }
+
+    // Account infos share their lamports and data with the runtime, so
+    // anything read between CPIs (e.g. the multisig signer's balance)
+    // reflects the previous CPI rather than the pre-transaction state.
+    // The `Transaction` account itself is never passed to the CPIs. The
+    // multisig account may be, by the `Auth` setters, so callers reload it
+    // afterwards with `record_execution` rather than writing back their copy.
+    //
+    // A failed CPI aborts the whole Solana transaction, rolling back anything
+    // the program could record about it, so each instruction is logged before
+    // it is invoked: the last such log of a failed execution names the
+    // instruction that failed, next to the program's own error logs.
+    for (i, ix) in ixs.iter().enumerate() {
+        msg!(
+            "Executing instruction {} ({})",
+            first_index + i,
+            ix.program_id
+        );
+        let infos = accounts.instruction_accounts(ix)?;
+        solana_program::program::invoke_signed(ix, &infos, &signer)?;
+    }
+    Ok(())
+}
+
+// See `multisig::set_owners`.
+fn replace_owners(multisig: &mut ProgramAccount<Multisig>, owners: Vec<Pubkey>) -> Result<()> {
+    check_owners(&owners)?;
+    let (owners, pending_owners): (Vec<Pubkey>, Vec<Pubkey>) = owners
+        .into_iter()
+        .partition(|o| multisig.owners.contains(o));
+    if owners.is_empty() {
+        return Err(ErrorCode::InvalidOwnersLen.into());
+    }
+    multisig.pending_owners = pending_owners;
+    multisig.owner_weights.retain(|w| owners.contains(&w.owner));
+    multisig
+        .owner_permissions
+        .retain(|p| owners.contains(&p.owner));
+    multisig.required_approvers.retain(|r| owners.contains(r));
+    for g in multisig.approval_groups.iter_mut() {
+        g.members.retain(|m| owners.contains(m));
+    }
+    if matches!(&multisig.spending_limit, Some(l) if !owners.contains(&l.owner)) {
+        multisig.spending_limit = None;
+    }
+    multisig.owners = owners;
+    let total_weight = multisig.total_weight()?;
+    // Pending owners count once each when they accept.
+    let reachable = total_weight
+        .checked_add(multisig.pending_owners.len() as u64)
+        .ok_or(ErrorCode::Overflow)?;
+    let m = &mut **multisig;
+    lower_threshold(
+        &mut m.threshold,
+        &mut m.pending_threshold,
+        total_weight,
+        reachable,
+    );
+    m.apply_threshold_percent()?;
+    if m.threshold_percent != 0 {
+        m.pending_threshold = 0;
+    }
+    m.pending_tier_thresholds.resize(m.threshold_tiers.len(), 0);
+    for (t, pending) in m
+        .threshold_tiers
+        .iter_mut()
+        .zip(m.pending_tier_thresholds.iter_mut())
+    {
+        lower_threshold(&mut t.threshold, pending, total_weight, reachable);
+    }
+    if m.pending_tier_thresholds.iter().all(|p| *p == 0) {
+        m.pending_tier_thresholds.clear();
+    }
+    lower_threshold(
+        &mut m.authority_transfer_threshold,
+        &mut m.pending_authority_transfer_threshold,
+        total_weight,
+        reachable,
+    );
+    if multisig.freeze_threshold > multisig.owners.len() as u64 {
+        multisig.freeze_threshold = multisig.owners.len() as u64;
+    }
+    multisig.check_approval_groups()?;
+    multisig.owner_set_seqno += 1;
+
+    emit!(OwnersChanged {
+        multisig: *multisig.to_account_info().key,
+        owners: multisig.owners.clone(),
+        threshold: multisig.threshold,
+        owner_set_seqno: multisig.owner_set_seqno,
+    });
+    Ok(())
+}
+
+// Lowers a threshold to what the owners' total weight can reach. If owners
+// yet to accept would reach more of the threshold that was asked for, it is
+// kept in `pending` for `raise_threshold` to restore as they accept.
+fn lower_threshold(threshold: &mut u64, pending: &mut u64, total_weight: u64, reachable: u64) {
+    let requested = if *pending > 0 { *pending } else { *threshold };
+    *threshold = requested.min(total_weight);
+    *pending = if *threshold < requested.min(reachable) {
+        requested.min(reachable)
+    } else {
+        0
+    };
+}
+
+// Raises a threshold `lower_threshold` lowered back towards the pending one,
+// as far as the owners' total weight allows.
+fn raise_threshold(threshold: &mut u64, pending: &mut u64, total_weight: u64) {
+    if *pending == 0 {
+        return;
+    }
+    *threshold = (*pending).min(total_weight);
+    if *threshold == *pending {
+        *pending = 0;
+    }
+}
+
+// See `multisig::change_threshold`.
+fn update_threshold(multisig: &mut ProgramAccount<Multisig>, threshold: u64) -> Result<()> {
+    if threshold == 0 || threshold > multisig.total_weight()? {
+        return Err(ErrorCode::InvalidThreshold.into());
+    }
+    multisig.threshold = threshold;
+    multisig.threshold_percent = 0;
+    multisig.pending_threshold = 0;
+    multisig.owner_set_seqno += 1;
+
+    emit!(OwnersChanged {
+        multisig: *multisig.to_account_info().key,
+        owners: multisig.owners.clone(),
+        threshold: multisig.threshold,
+        owner_set_seqno: multisig.owner_set_seqno,
+    });
+    Ok(())
+}
+
+// Applies a config change proposed with `propose_config_change`.
+fn apply_config_action(
+    multisig: &mut ProgramAccount<Multisig>,
+    action: ConfigAction,
+) -> Result<()> {
+    match action {
+        ConfigAction::AddOwner { owner } => {
+            let mut owners = multisig.owners.clone();
+            owners.extend(multisig.pending_owners.iter().cloned());
+            owners.push(owner);
+            replace_owners(multisig, owners)
+        }
+        ConfigAction::RemoveOwner { owner } => {
+            if !multisig.owners.contains(&owner) {
+                return Err(ErrorCode::InvalidOwner.into());
+            }
+            let mut owners = multisig.owners.clone();
+            owners.retain(|o| o != &owner);
+            owners.extend(multisig.pending_owners.iter().cloned());
+            replace_owners(multisig, owners)
+        }
+        ConfigAction::ChangeThreshold { threshold } => update_threshold(multisig, threshold),
+        ConfigAction::SetTimelock { timelock_seconds } => {
+            if timelock_seconds < 0 {
+                return Err(ErrorCode::InvalidTimelock.into());
+            }
+            multisig.timelock_seconds = timelock_seconds;
+            Ok(())
+        }
+    }
+}
+
+// The `Auth` instructions the given config changes stand for, each applied
+// after the previous ones, so that they are held to the same policies as if
+// they had been proposed as instructions: known programs, deny rules,
+// validators and threshold tiers.
+fn config_instructions(
+    program_id: &Pubkey,
+    multisig: &ProgramAccount<Multisig>,
+    actions: &[ConfigAction],
+) -> Result<Vec<Instruction>> {
+    if actions.is_empty() {
+        return Ok(Vec::new());
+    }
+    let multisig_key = multisig.to_account_info().key;
+    let accounts = vec![
+        AccountMeta::new(*multisig_key, false),
+        AccountMeta::new_readonly(multisig.signer(multisig_key, program_id)?, true),
+    ];
+    let mut owners = multisig.owners.clone();
+    owners.extend(multisig.pending_owners.iter().cloned());
+    let mut ixs = Vec::with_capacity(actions.len());
+    for action in actions.iter() {
+        let data = match action {
+            ConfigAction::AddOwner { owner } => {
+                owners.push(*owner);
+                instruction::SetOwners {
+                    owners: owners.clone(),
+                }
+                .data()
+            }
+            ConfigAction::RemoveOwner { owner } => {
+                owners.retain(|o| o != owner);
+                instruction::SetOwners {
+                    owners: owners.clone(),
+                }
+                .data()
+            }
+            ConfigAction::ChangeThreshold { threshold } => instruction::ChangeThreshold {
+                threshold: *threshold,
+            }
+            .data(),
+            ConfigAction::SetTimelock { timelock_seconds } => instruction::SetTimelock {
+                timelock_seconds: *timelock_seconds,
+            }
+            .data(),
+        };
+        ixs.push(Instruction {
+            program_id: *program_id,
+            accounts: accounts.clone(),
+            data,
+        });
+    }
+    Ok(ixs)
+}
+
+// The distinct owners of the multisig among the signers of the given accounts.
+fn signing_owners(multisig: &Multisig, accounts: &[AccountInfo]) -> Vec<Pubkey> {
+    let mut owners: Vec<Pubkey> = Vec::new();
+    for a in accounts.iter().filter(|a| a.is_signer) {
+        if multisig.owners.contains(a.key) && !owners.contains(a.key) {
+            owners.push(*a.key);
+        }
+    }
+    owners
+}
+
+// Records a successful execution on the multisig. The executed instructions
+// may have changed the multisig account itself (e.g. through the `Auth`
+// setters), so it is reloaded first rather than overwritten with the stale
+// copy deserialized before execution.
+fn record_execution(multisig: &mut ProgramAccount<Multisig>) -> Result<()> {
+    let reloaded = {
+        let info = multisig.to_account_info();
+        let data = info.try_borrow_data()?;
+        Multisig::try_deserialize(&mut &data[..])?
+    };
+    **multisig = reloaded;
+    multisig.last_executed_at = Clock::get()?.unix_timestamp;
+    Ok(())
+}
+
+// Closes a program owned account, moving its lamports to `destination`. The
+// data is zeroed so the account can't be used again within the same
+// transaction.
+fn close_account(account: &AccountInfo, destination: &AccountInfo) -> Result<()> {
+    account.try_borrow_mut_data()?.fill(0);
+    let lamports = account.lamports();
+    **destination.try_borrow_mut_lamports()? = destination
+        .lamports()
+        .checked_add(lamports)
+        .ok_or(ErrorCode::Overflow)?;
+    **account.try_borrow_mut_lamports()? = 0;
+    Ok(())
+}
+
+// Initializes a new transaction account for the given instructions,
+// automatically signed by the proposer, which must be one of the owners of the
+// multisig, and charges the proposal fee.
+fn propose<'info>(
+    program_id: &Pubkey,
+    multisig: &mut ProgramAccount<'info, Multisig>,
+    tx: &mut ProgramAccount<'info, Transaction>,
+    proposer: &AccountInfo<'info>,
+    remaining_accounts: &[AccountInfo<'info>],
+    ixs: Vec<Instruction>,
+) -> Result<()> {
+    check_global_pause(program_id, remaining_accounts)?;
+    multisig.check_not_frozen()?;
+    if let Some(expires_at) = tx.expires_at {
+        if expires_at <= Clock::get()?.unix_timestamp {
+            return Err(ErrorCode::InvalidExpiry.into());
+        }
+    }
+    let config_ixs = config_instructions(program_id, multisig, &tx.config_actions)?;
+    let checked = [&ixs[..], &config_ixs[..]].concat();
+    for ix in checked.iter() {
+        multisig.check_known_instruction(&ix.program_id, &ix.data)?;
+        multisig.check_not_denied(&ix.program_id, &ix.data)?;
+    }
+    multisig.validate(program_id, &checked)?;
+
+    multisig.check_permission(proposer.key, PERMISSION_PROPOSE)?;
+
+    // Charge the proposal fee, if any. The treasury and the system program
+    // are given as remaining accounts.
+    let mut fee = 0;
+    if let Some(treasury) = multisig.fee_treasury {
+        if multisig.proposal_fee_lamports > 0 {
+            fee = multisig.proposal_fee_lamports;
+            let accounts = TransactionAccountMap::new(remaining_accounts);
+            let ix = system_instruction::transfer(proposer.key, &treasury, fee);
+            solana_program::program::invoke(
+                &ix,
+                &[
+                    proposer.clone(),
+                    accounts
+                        .get(&treasury)
+                        .ok_or(ErrorCode::MissingAccount)?
+                        .clone(),
+                    accounts
+                        .get(&system_program::ID)
+                        .ok_or(ErrorCode::MissingAccount)?
+                        .clone(),
+                ],
+            )?;
+        }
+    }
+
+    tx.program_id = ixs.iter().map(|ix| ix.program_id).collect();
+    tx.accounts = ixs
+        .iter()
+        .map(|ix| ix.accounts.iter().cloned().map(Into::into).collect())
+        .collect();
+    tx.data = ixs.into_iter().map(|ix| ix.data).collect();
+    let now = Clock::get()?.unix_timestamp;
+    tx.approvals = Vec::new();
+    tx.rejectors = Vec::new();
+    // Only proposers who may approve count as approving their proposal.
+    if multisig.permissions_of(proposer.key) & PERMISSION_APPROVE != 0 {
+        tx.approve(proposer.key, now);
+    }
+    tx.status = TransactionStatus::Active;
+    tx.finalized_at = None;
+    tx.executor = None;
+    tx.threshold_reached_at = None;
+    tx.update_threshold_reached(multisig, now)?;
+    tx.multisig = *multisig.to_account_info().key;
+    tx.proposer = *proposer.key;
+    tx.fee_paid = fee;
+    tx.owner_set_seqno = multisig.owner_set_seqno;
+    tx.index = multisig.transaction_count;
+    tx.created_at = now;
+    multisig.transaction_count = multisig
+        .transaction_count
+        .checked_add(1)
+        .ok_or(ErrorCode::Overflow)?;
+
+    emit!(TransactionCreated {
+        multisig: tx.multisig,
+        transaction: *tx.to_account_info().key,
+        index: tx.index,
+        proposer: tx.proposer,
+        fee,
+    });
+
+    record_change(
+        program_id,
+        &tx.multisig,
+        remaining_accounts,
+        CHANGE_PROPOSED,
+        proposer.key,
+        Some(*tx.to_account_info().key),
+    )
+}
+
+#[derive(Accounts)]
+pub struct CreateMultisig<'info> {
+    #[account(init)]
+    multisig: ProgramAccount<'info, Multisig>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateFromSquads<'info> {
+    #[account(init)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account("squads_multisig.owner == &squads_v3::ID")]
+    squads_multisig: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateAndAssume<'info> {
+    #[account(init)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // The new upgrade authority. Checked in the handler.
+    multisig_signer: AccountInfo<'info>,
+    #[account(mut)]
+    program_data: AccountInfo<'info>,
+    // The current upgrade authority of the program.
+    #[account(signer)]
+    upgrade_authority: AccountInfo<'info>,
+    #[account("loader.key == &bpf_loader_upgradeable::ID")]
+    loader: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateTransaction<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct CreateTransactionPda<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // Created by the handler at `pda::transaction`.
+    #[account(mut)]
+    transaction: AccountInfo<'info>,
+    // One of the owners, paying for the transaction account. Checked in the
+    // handler.
+    #[account(mut, signer)]
+    proposer: AccountInfo<'info>,
+    #[account("system_program.key == &system_program::ID")]
+    system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EditDraft<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(
+        mut,
+        belongs_to = multisig,
+        "transaction.status == TransactionStatus::Draft",
+        "&transaction.proposer == proposer.key"
+    )]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The draft's proposer.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateInstructionBuffer<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    buffer: Loader<'info, InstructionBuffer>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct WriteInstructionBuffer<'info> {
+    #[account(mut)]
+    buffer: Loader<'info, InstructionBuffer>,
+    // The buffer's proposer. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateTransactionFromBuffer<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    // Locked by the handler.
+    #[account(mut)]
+    buffer: Loader<'info, InstructionBuffer>,
+    // The buffer's proposer. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct BatchCreate<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeCloseAta<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    #[account("token_account.owner == &spl_token::ID")]
+    token_account: AccountInfo<'info>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeTokenTransfer<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The multisig signer's associated token account of the mint, which must
+    // exist. Checked in the handler.
+    token_account: AccountInfo<'info>,
+    #[account("destination.to_account_info().owner == &spl_token::ID")]
+    destination: CpiAccount<'info, TokenAccount>,
+    // The token program the transfer invokes.
+    #[account("token_program.key == &spl_token::ID")]
+    token_program: AccountInfo<'info>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> ProposeTokenTransfer<'info> {
+    // Returns the multisig signer and its associated token account of the
+    // given mint, the source, which must be of the same mint as the
+    // destination.
+    fn signer_token_accounts(
+        &self,
+        program_id: &Pubkey,
+        mint: &Pubkey,
+        destination: &Pubkey,
+    ) -> Result<(Pubkey, spl_token::state::Account)> {
+        let signer = self
+            .multisig
+            .signer(self.multisig.to_account_info().key, program_id)?;
+        let address = associated_token::address(&signer, mint);
+        if self.token_account.key != &address
+            || destination != self.destination.to_account_info().key
+        {
+            return Err(ErrorCode::InvalidTokenAccount.into());
+        }
+        if self.token_account.owner != &spl_token::ID || self.token_account.data_is_empty() {
+            msg!(
+                "Create the multisig signer's associated token account {} first",
+                address
+            );
+            return Err(ErrorCode::SignerPdaAtaNotFound.into());
+        }
+        let account = spl_token::state::Account::unpack(&self.token_account.try_borrow_data()?)?;
+        if account.owner != signer {
+            return Err(ErrorCode::InvalidTokenAccountOwner.into());
+        }
+        if self.destination.mint != account.mint {
+            return Err(ErrorCode::TokenMintMismatch.into());
+        }
+        Ok((signer, account))
+    }
+}
+
+impl<'info> ProposeCloseAta<'info> {
+    // Returns the multisig signer and the given token account, which must be
+    // owned by it.
+    fn signer_token_account(
+        &self,
+        program_id: &Pubkey,
+        token_account: &Pubkey,
+    ) -> Result<(Pubkey, spl_token::state::Account)> {
+        if token_account != self.token_account.key {
+            return Err(ErrorCode::InvalidTokenAccount.into());
+        }
+        let signer = self
+            .multisig
+            .signer(self.multisig.to_account_info().key, program_id)?;
+        let account = spl_token::state::Account::unpack(&self.token_account.try_borrow_data()?)?;
+        if account.owner != signer {
+            return Err(ErrorCode::InvalidTokenAccountOwner.into());
+        }
+        Ok((signer, account))
+    }
+}
+
+#[derive(Accounts)]
+pub struct ProposeProgramUpgrade<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The buffer holding the new program. Checked in the handler.
+    buffer: AccountInfo<'info>,
+    // The program data account of the program to upgrade. Checked in the
+    // handler.
+    program_data: AccountInfo<'info>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeSetUpgradeAuthority<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The program data account of the program. Checked in the handler.
+    program_data: AccountInfo<'info>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeMintAuthority<'info> {
+    // Mutable to count the transaction.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(init)]
+    transaction: ProgramAccount<'info, Transaction>,
+    #[account("mint.owner == &spl_token::ID")]
+    mint: AccountInfo<'info>,
+    // One of the owners. Checked in the handler.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+}
+
+impl<'info> ProposeMintAuthority<'info> {
+    // Returns the multisig signer and the mint.
+    fn signer_mint(&self, program_id: &Pubkey) -> Result<(Pubkey, spl_token::state::Mint)> {
+        let signer = self
+            .multisig
+            .signer(self.multisig.to_account_info().key, program_id)?;
+        let mint = spl_token::state::Mint::unpack(&self.mint.try_borrow_data()?)?;
+        Ok((signer, mint))
+    }
+}
+
+#[derive(Accounts)]
+pub struct Approve<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(
+        mut,
+        belongs_to = multisig,
+        "transaction.owner_set_seqno == multisig.owner_set_seqno"
+    )]
+    transaction: ProgramAccount<'info, Transaction>,
+    // One of the multisig owners. Checked in the handler.
+    #[account(signer)]
+    owner: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct BatchApprove<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    // One of the multisig owners. Checked in the handler.
+    #[account(signer)]
+    owner: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ApproveWithSignature<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(
+        mut,
+        belongs_to = multisig,
+        "transaction.owner_set_seqno == multisig.owner_set_seqno"
+    )]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The instructions sysvar. Checked in the handler.
+    instructions: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct DeleteTransaction<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    // The `Transaction` to close. Checked in the handler.
+    #[account(mut)]
+    transaction: AccountInfo<'info>,
+    // The transaction's proposer, receiving its rent.
+    #[account(mut, signer)]
+    proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EditTransaction<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(mut, belongs_to = multisig, "&transaction.proposer == proposer.key")]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The transaction's proposer.
+    #[account(signer)]
+    proposer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CloseTransaction<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    // The `Transaction` to close. Checked in the handler.
+    #[account(mut)]
+    transaction: AccountInfo<'info>,
+    // Any owner, or anyone after the grace period. Checked in the handler.
+    #[account(signer)]
+    closer: AccountInfo<'info>,
+    // Receives the rent. Checked in the handler.
+    #[account(mut)]
+    destination: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitTransactionHistory<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    // Created by the handler at `pda::history`.
+    #[account(mut)]
+    history: AccountInfo<'info>,
+    #[account(mut, signer)]
+    payer: AccountInfo<'info>,
+    #[account("system_program.key == &system_program::ID")]
+    system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitChangelog<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    // Created by the handler at `pda::changelog`.
+    #[account(mut)]
+    changelog: AccountInfo<'info>,
+    #[account(mut, signer)]
+    payer: AccountInfo<'info>,
+    #[account("system_program.key == &system_program::ID")]
+    system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteTransaction<'info> {
+    // Mutable to record the time of execution.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+    #[account(
+        mut,
+        belongs_to = multisig,
+        "transaction.owner_set_seqno == multisig.owner_set_seqno"
+    )]
+    transaction: ProgramAccount<'info, Transaction>,
+    // Anyone, unless the multisig restricts execution. Pays the execution fee
+    // and receives the executor tip, if any.
+    #[account(mut, signer)]
+    executor: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SpendLamports<'info> {
+    // Mutable to track the spent allowance.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(mut, seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+    // The owner of the spending limit. Checked in the handler.
+    #[account(signer)]
+    owner: AccountInfo<'info>,
+    #[account(mut)]
+    destination: AccountInfo<'info>,
+    #[account("system_program.key == &system_program::ID")]
+    system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct SpendTokens<'info> {
+    // Mutable to track the spent allowance.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+    // The owner of the spending limit. Checked in the handler.
+    #[account(signer)]
+    owner: AccountInfo<'info>,
+    // A token account of the multisig signer. Checked in the handler.
+    #[account(mut, "source.owner == &spl_token::ID")]
+    source: AccountInfo<'info>,
+    #[account(mut)]
+    destination: AccountInfo<'info>,
+    #[account("token_program.key == &spl_token::ID")]
+    token_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExecuteBatch<'info> {
+    // Mutable to record the time of execution.
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+    // Anyone, unless the multisig restricts execution. Pays the execution fees
+    // and receives the executor tips, if any.
+    #[account(mut, signer)]
+    executor: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ColdRecovery<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+    // The multisig's cold recovery key. Checked in the handler.
+    #[account(signer)]
+    recovery_key: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Veto<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(mut, belongs_to = multisig)]
+    transaction: ProgramAccount<'info, Transaction>,
+    // The multisig's veto authority. Checked in the handler.
+    #[account(signer)]
+    veto_authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Freeze<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptOwnership<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // One of the multisig's pending owners. Checked in the handler.
+    #[account(signer)]
+    new_owner: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct RotateOwnerKey<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // One of the multisig owners. Checked in the handler.
+    #[account(signer)]
+    owner: AccountInfo<'info>,
+    // The key replacing the owner's.
+    #[account(signer)]
+    new_owner: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Recovery<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+}
+
+#[derive(Accounts)]
+pub struct CancelRecovery<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // One of the multisig owners. Checked in the handler.
+    #[account(signer)]
+    owner: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ValidateAccounts<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(mut, belongs_to = multisig)]
+    transaction: ProgramAccount<'info, Transaction>,
+}
+
+#[derive(Accounts)]
+pub struct Auth<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(signer, seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct UpgradeSelf<'info> {
+    #[account(mut)]
+    multisig: ProgramAccount<'info, Multisig>,
+    // The program's upgrade authority.
+    #[account(signer, seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+    #[account(mut)]
+    program_data: AccountInfo<'info>,
+    #[account(mut, "program.key == program_id")]
+    program: AccountInfo<'info>,
+    #[account(mut)]
+    buffer: AccountInfo<'info>,
+    // Receives the buffer's lamports.
+    #[account(mut)]
+    spill: AccountInfo<'info>,
+    rent: Sysvar<'info, Rent>,
+    clock: Sysvar<'info, Clock>,
+    #[account("loader.key == &bpf_loader_upgradeable::ID")]
+    loader: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ComputeVotingPower<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+}
+
+#[derive(Accounts)]
+pub struct GetSignerInfo<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(seeds = [
+        multisig.to_account_info().key.as_ref(),
+        &[multisig.nonce],
+    ])]
+    multisig_signer: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct InitGlobalPause<'info> {
+    // Created by the handler at `pda::global_pause`.
+    #[account(mut)]
+    global_pause: AccountInfo<'info>,
+    #[account("program.key == program_id")]
+    program: AccountInfo<'info>,
+    // The program's data account. Checked in the handler.
+    program_data: AccountInfo<'info>,
+    #[account(mut, signer)]
+    upgrade_authority: AccountInfo<'info>,
+    #[account("system_program.key == &system_program::ID")]
+    system_program: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GlobalPauseAuth<'info> {
+    #[account(mut, has_one = authority)]
+    global_pause: ProgramAccount<'info, GlobalPause>,
+    #[account(signer)]
+    authority: AccountInfo<'info>,
+}
+
+#[derive(Accounts)]
+pub struct Noop<'info> {
+    multisig: ProgramAccount<'info, Multisig>,
+    #[account(signer)]
+    payer: AccountInfo<'info>,
+}
+
+#[account]
+pub struct Multisig {
+    owners: Vec<Pubkey>,
+    threshold: u64,
+    nonce: u8,
+    // Programs whose instructions must start with an allowed discriminant.
+    known_programs: Vec<KnownProgram>,
+    // Bitmask of the `VALIDATOR_*` policies enforced on new transactions.
+    validators_enabled: u32,
+    // Largest system transfer allowed by the `VALIDATOR_MAX_VALUE` policy.
+    max_transfer_lamports: u64,
+    // Lamports charged to the proposer of each transaction.
+    proposal_fee_lamports: u64,
+    // Recipient of the proposal fee. No fee is charged if not set.
+    fee_treasury: Option<Pubkey>,
+    // Refund the proposal fee once the transaction executes.
+    refund_fee_on_execute: bool,
+    // Lamports charged to the executor per executed instruction, paid
+    // to `fee_treasury`.
+    execution_compute_fee_lamports_per_ix: u64,
+    // Hash of the program bits `upgrade_self` may upgrade the program to.
+    upgrade_buffer_hash: Option<[u8; 32]>,
+    // Key allowed to execute without approvals once the multisig has been
+    // inactive for `COLD_RECOVERY_GRACE_PERIOD`. Only set on creation.
+    cold_recovery_key: Option<Pubkey>,
+    // Unix timestamp of the last execution, or of the creation if nothing has
+    // been executed yet.
+    last_executed_at: i64,
+    // Most instructions per transaction allowed by the
+    // `VALIDATOR_INSTRUCTION_COUNT` policy. Zero means `MAX_INSTRUCTIONS`.
+    max_instructions_global: u8,
+    // Incremented whenever the owners or threshold change, invalidating
+    // transactions proposed before.
+    owner_set_seqno: u32,
+    // Receives the rent of closed transactions. Their proposer if not set.
+    close_destination: Option<Pubkey>,
+    // Seconds between a transaction reaching the threshold and the earliest
+    // time it may execute.
+    timelock_seconds: i64,
+    // Voting weights of owners that don't count once. The threshold is
+    // compared against the summed weight of the approvers.
+    owner_weights: Vec<OwnerWeight>,
+    // Permissions of owners that may not do everything.
+    owner_permissions: Vec<OwnerPermissions>,
+    // Only owners with `PERMISSION_EXECUTE` may execute, rather than anyone.
+    restrict_execution: bool,
+    // Allowance a single owner may spend without a proposal.
+    spending_limit: Option<SpendingLimit>,
+    // Number of transactions proposed so far, and the index of the next one.
+    transaction_count: u64,
+    // Lamports paid by the multisig signer to the executor of each
+    // transaction.
+    executor_tip_lamports: u64,
+    // Only transactions with an index of at least `next_execution_index` may
+    // execute.
+    sequential_execution: bool,
+    // One past the index of the latest proposed transaction executed so far.
+    next_execution_index: u64,
+    // Owners whose approval every transaction needs, whatever the threshold.
+    required_approvers: Vec<Pubkey>,
+    // Groups of owners each transaction needs a quorum of approvals from.
+    approval_groups: Vec<ApprovalGroup>,
+    // Key allowed to cancel any pending transaction, if any.
+    veto_authority: Option<Pubkey>,
+    // Whether the multisig is frozen, see `freeze`.
+    frozen: bool,
+    // Key allowed to freeze the multisig on its own, if any.
+    freeze_guardian: Option<Pubkey>,
+    // Number of owners that may freeze the multisig together, or zero if
+    // owners can't.
+    freeze_threshold: u64,
+    // Keys allowed to replace inactive owners, see `initiate_recovery`.
+    guardians: Vec<Pubkey>,
+    // Number of guardians that must sign a recovery.
+    guardian_threshold: u64,
+    // Seconds without executions after which guardians may start a recovery.
+    recovery_inactivity_period: i64,
+    // Seconds between starting and completing a recovery.
+    recovery_delay: i64,
+    // The recovery guardians started, if any.
+    pending_recovery: Option<PendingRecovery>,
+    // Keys added by `set_owners` that haven't accepted ownership yet.
+    pending_owners: Vec<Pubkey>,
+    // Programs allowed by the `VALIDATOR_ALLOWLIST` policy on top of the
+    // `known_programs`, with no restriction on their instructions.
+    allowed_programs: Vec<Pubkey>,
+    // Instructions transactions may not invoke.
+    deny_rules: Vec<DenyRule>,
+    // Thresholds for transactions invoking some instructions.
+    threshold_tiers: Vec<ThresholdTier>,
+    // Percentage of the total weight the threshold follows, or zero if the
+    // threshold is absolute.
+    threshold_percent: u8,
+    // Threshold and timelock for handing off an upgrade authority, see
+    // `set_authority_transfer_policy`.
+    authority_transfer_threshold: u64,
+    authority_transfer_timelock: i64,
+    // Threshold `set_owners` had to lower because of owners yet to accept,
+    // restored as they do. Zero if none.
+    pending_threshold: u64,
+    // The same for each of the `threshold_tiers`, empty if none is pending.
+    pending_tier_thresholds: Vec<u64>,
+    // The same for the `authority_transfer_threshold`.
+    pending_authority_transfer_threshold: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ThresholdTier {
+    program_id: Pubkey,
+    // Instructions whose data starts with these bytes need the tier's
+    // threshold, all of the program's if empty.
+    data_prefix: Vec<u8>,
+    threshold: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct DenyRule {
+    program_id: Pubkey,
+    // Instructions whose data starts with these bytes are denied, all of the
+    // program's if empty.
+    data_prefix: Vec<u8>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PendingRecovery {
+    // The owners and threshold replacing the current ones.
+    owners: Vec<Pubkey>,
+    threshold: u64,
+    // Unix timestamp the recovery was started at.
+    started_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ApprovalGroup {
+    // Label of the group, e.g. "finance", at most `MAX_GROUP_NAME_LEN` bytes.
+    name: String,
+    // Owners belonging to the group.
+    members: Vec<Pubkey>,
+    // Number of members that must approve a transaction.
+    quorum: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SpendingLimit {
+    // The owner allowed to spend.
+    owner: Pubkey,
+    // Mint of the spendable tokens, or the multisig signer's lamports if not
+    // set.
+    mint: Option<Pubkey>,
+    // Most that may be spent per period.
+    amount: u64,
+    // Length of a period in seconds.
+    period: i64,
+    // Spent so far in the current period.
+    spent: u64,
+    // Unix timestamp the current period started at.
+    period_start: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OwnerPermissions {
+    owner: Pubkey,
+    // Bitmask of `PERMISSION_*`.
+    permissions: u8,
+}
+
+// Bits of `OwnerPermissions::permissions`.
+pub const PERMISSION_PROPOSE: u8 = 1 << 0;
+pub const PERMISSION_APPROVE: u8 = 1 << 1;
+pub const PERMISSION_EXECUTE: u8 = 1 << 2;
+pub const PERMISSION_ALL: u8 = PERMISSION_PROPOSE | PERMISSION_APPROVE | PERMISSION_EXECUTE;
+
+// Actions recorded in a `MultisigChangelog`.
+pub const CHANGE_PROPOSED: u8 = 1;
+pub const CHANGE_APPROVED: u8 = 2;
+pub const CHANGE_REJECTED: u8 = 3;
+pub const CHANGE_VETOED: u8 = 4;
+pub const CHANGE_EXECUTED: u8 = 5;
+// An execution changed the owners, e.g. with `set_owners`.
+pub const CHANGE_OWNERS_CHANGED: u8 = 6;
+pub const CHANGE_OWNER_ACCEPTED: u8 = 7;
+pub const CHANGE_OWNER_KEY_ROTATED: u8 = 8;
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct OwnerWeight {
+    owner: Pubkey,
+    weight: u64,
+}
+
+impl Multisig {
+    // Bytes a multisig account needs, discriminator included, as
+    // `create_multisig` initializes it with the given number of owners and a
+    // cold recovery key. Anything set later, such as the optional keys, the
+    // weights, policies, guardians or pending owners, needs room on top, and
+    // the account can't be resized, so allocate it with room to spare.
+    pub fn space(owners: usize) -> usize {
+        MULTISIG_BASE_SPACE + 32 * owners
+    }
+
+    // The voting weight of the given owner.
+    fn weight_of(&self, owner: &Pubkey) -> u64 {
+        self.owner_weights
+            .iter()
+            .find(|w| &w.owner == owner)
+            .map(|w| w.weight)
+            .unwrap_or(1)
+    }
+
+    // The weight the given owner's approval carries: its voting weight, or
+    // nothing without `PERMISSION_APPROVE`.
+    fn approval_weight_of(&self, owner: &Pubkey) -> u64 {
+        if self.permissions_of(owner) & PERMISSION_APPROVE == 0 {
+            return 0;
+        }
+        self.weight_of(owner)
+    }
+
+    // The number of owners' votes required to execute a transaction.
+    pub fn threshold(&self) -> u64 {
+        self.threshold
+    }
+
+    // The bump of the multisig signer PDA.
+    pub fn nonce(&self) -> u8 {
+        self.nonce
+    }
+
+    // The `PERMISSION_*` mask of the given owner.
+    fn permissions_of(&self, owner: &Pubkey) -> u8 {
+        self.owner_permissions
+            .iter()
+            .find(|p| &p.owner == owner)
+            .map(|p| p.permissions)
+            .unwrap_or(PERMISSION_ALL)
+    }
+
+    // Records the given owner spending the given amount of the given mint,
+    // starting a new period of the spending limit if the current one is over.
+    fn spend_allowance(
+        &mut self,
+        owner: &Pubkey,
+        mint: Option<Pubkey>,
+        amount: u64,
+        now: i64,
+    ) -> Result<()> {
+        if !self.owners.contains(owner) {
+            return Err(ErrorCode::InvalidOwner.into());
+        }
+        let limit = match &mut self.spending_limit {
+            Some(limit) if &limit.owner == owner && limit.mint == mint => limit,
+            _ => return Err(ErrorCode::NoSpendingLimit.into()),
+        };
+        let elapsed = now
+            .checked_sub(limit.period_start)
+            .ok_or(ErrorCode::Overflow)?;
+        if elapsed >= limit.period {
+            // Periods stay aligned to the first one, whatever the gaps between
+            // spends.
+            limit.period_start = now - elapsed % limit.period;
+            limit.spent = 0;
+        }
+        let spent = limit.spent.checked_add(amount).ok_or(ErrorCode::Overflow)?;
+        if spent > limit.amount {
+            return Err(ErrorCode::SpendingLimitExceeded.into());
+        }
+        limit.spent = spent;
+        Ok(())
+    }
+
+    // Fails unless the given key is an owner with the given permission.
+    fn check_permission(&self, owner: &Pubkey, permission: u8) -> Result<()> {
+        if !self.owners.contains(owner) {
+            return Err(ErrorCode::InvalidOwner.into());
+        }
+        if self.permissions_of(owner) & permission == 0 {
+            return Err(ErrorCode::MissingPermission.into());
+        }
+        Ok(())
+    }
+
+    // The approval weight needed to execute the given instructions: the
+    // highest of the multisig's threshold, the tiers they match and the
+    // authority transfer threshold if they hand off an upgrade authority.
+    fn threshold_for(&self, instructions: &[Instruction]) -> u64 {
+        let mut threshold = self.threshold;
+        for ix in instructions.iter() {
+            if is_upgrade_authority_transfer(ix) {
+                threshold = threshold.max(self.authority_transfer_threshold);
+            }
+            for t in self.threshold_tiers.iter() {
+                if t.program_id == ix.program_id && ix.data.starts_with(&t.data_prefix) {
+                    threshold = threshold.max(t.threshold);
+                }
+            }
+        }
+        threshold
+    }
+
+    // Recomputes the threshold from `threshold_percent`, if set.
+    fn apply_threshold_percent(&mut self) -> Result<()> {
+        if self.threshold_percent == 0 {
+            return Ok(());
+        }
+        let threshold = self
+            .total_weight()?
+            .checked_mul(self.threshold_percent as u64)
+            .ok_or(ErrorCode::Overflow)?
+            .checked_add(99)
+            .ok_or(ErrorCode::Overflow)?
+            / 100;
+        self.threshold = threshold.max(1);
+        Ok(())
+    }
+
+    // Fails unless every threshold tier can be reached by the owners.
+    fn check_threshold_tiers(&self) -> Result<()> {
+        let total_weight = self.total_weight()?;
+        for t in self.threshold_tiers.iter() {
+            if t.threshold == 0 || t.threshold > total_weight {
+                return Err(ErrorCode::InvalidThreshold.into());
+            }
+        }
+        Ok(())
+    }
+
+    // Fails if a deny rule matches the given instruction.
+    fn check_not_denied(&self, program_id: &Pubkey, data: &[u8]) -> Result<()> {
+        let denied = self
+            .deny_rules
+            .iter()
+            .any(|r| &r.program_id == program_id && data.starts_with(&r.data_prefix));
+        if denied {
+            msg!("Instruction of {} denied", program_id);
+            return Err(ErrorCode::InstructionDenied.into());
+        }
+        Ok(())
+    }
+
+    // Fails if the multisig is frozen.
+    fn check_not_frozen(&self) -> Result<()> {
+        if self.frozen {
+            return Err(ErrorCode::MultisigFrozen.into());
+        }
+        Ok(())
+    }
+
+    // Fails unless every approval group has a well formed name and a quorum
+    // its members, all allowed to approve, can reach.
+    fn check_approval_groups(&self) -> Result<()> {
+        for g in self.approval_groups.iter() {
+            if g.name.len() > MAX_GROUP_NAME_LEN {
+                return Err(ErrorCode::InvalidApprovalGroup.into());
+            }
+            if g.quorum == 0 || g.quorum > g.members.len() as u64 {
+                msg!("Group {} can't reach its quorum", g.name);
+                return Err(ErrorCode::InvalidApprovalGroup.into());
+            }
+            for (i, m) in g.members.iter().enumerate() {
+                if g.members[..i].contains(m) {
+                    return Err(ErrorCode::DuplicateOwner.into());
+                }
+                self.check_permission(m, PERMISSION_APPROVE)?;
+            }
+        }
+        Ok(())
+    }
+
+    // The summed weight of all owners allowed to approve, the largest
+    // reachable threshold.
+    pub fn total_weight(&self) -> Result<u64> {
+        let mut weight: u64 = 0;
+        for owner in self.owners.iter() {
+            weight = weight
+                .checked_add(self.approval_weight_of(owner))
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        Ok(weight)
+    }
+
+    // The signer PDA of this multisig, given its address.
+    fn signer(&self, multisig: &Pubkey, program_id: &Pubkey) -> Result<Pubkey> {
+        Pubkey::create_program_address(&[multisig.as_ref(), &[self.nonce]], program_id)
+            .map_err(|_| ErrorCode::InvalidMultisigSigner.into())
+    }
+
+    // Checks an instruction targeting a known program uses one of its allowed
+    // discriminants. Instructions for any other program are not restricted.
+    fn check_known_instruction(&self, program_id: &Pubkey, data: &[u8]) -> Result<()> {
+        let known = match self
+            .known_programs
+            .iter()
+            .find(|k| &k.program_id == program_id)
+        {
+            None => return Ok(()),
+            Some(known) => known,
+        };
+        if known
+            .allowed_discriminants
+            .iter()
+            .any(|d| data.starts_with(&d[..]))
+        {
+            return Ok(());
+        }
+        Err(ErrorCode::UnknownInstruction.into())
+    }
+
+    // Runs every enabled validator against the given instructions.
+    fn validate(&self, program_id: &Pubkey, instructions: &[Instruction]) -> Result<()> {
+        let no_self_cpi = NoSelfCpiValidator {
+            program_id: *program_id,
+        };
+        let validators: [(u32, &dyn TransactionValidator); 5] = [
+            (VALIDATOR_ALLOWLIST, &AllowlistValidator),
+            (VALIDATOR_MAX_VALUE, &MaxValueValidator),
+            (VALIDATOR_DATA_SIZE, &DataSizeLimiter),
+            (VALIDATOR_INSTRUCTION_COUNT, &InstructionCountLimiter),
+            (VALIDATOR_NO_SELF_CPI, &no_self_cpi),
+        ];
+        for (bit, validator) in validators.iter() {
+            if self.validators_enabled & bit != 0 {
+                validator.validate(self, instructions)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct KnownProgram {
+    program_id: Pubkey,
+    allowed_discriminants: Vec<[u8; 8]>,
+}
+
+// Bits of `Multisig::validators_enabled`.
+pub const VALIDATOR_ALLOWLIST: u32 = 1 << 0;
+pub const VALIDATOR_MAX_VALUE: u32 = 1 << 1;
+pub const VALIDATOR_DATA_SIZE: u32 = 1 << 2;
+pub const VALIDATOR_INSTRUCTION_COUNT: u32 = 1 << 3;
+pub const VALIDATOR_NO_SELF_CPI: u32 = 1 << 4;
+
+// Limits used by the `DataSizeLimiter` and `InstructionCountLimiter`.
+pub const MAX_INSTRUCTION_DATA_LEN: usize = 1024;
+pub const MAX_INSTRUCTIONS: usize = 10;
+
+// Longest memo a transaction may carry, in bytes.
+pub const MAX_MEMO_LEN: usize = 256;
+
+// Most owners a multisig may have.
+pub const MAX_OWNERS: usize = 10;
+
+// Longest name an approval group may have, in bytes.
+pub const MAX_GROUP_NAME_LEN: usize = 32;
+
+// Most transactions `batch_create_transactions` proposes at once, to stay
+// within the compute budget.
+pub const MAX_BATCH_SIZE: u8 = 5;
+
+// Seconds a multisig must be inactive before its cold recovery key may be
+// used, 90 days.
+pub const COLD_RECOVERY_GRACE_PERIOD: i64 = 90 * 24 * 60 * 60;
+
+// Seconds after execution or cancellation from which anyone, not only an
+// owner, may close a transaction, 7 days.
+pub const CLOSE_GRACE_PERIOD: i64 = 7 * 24 * 60 * 60;
+
+// Furthest in the future `set_transaction_eta` may move a transaction's ETA,
+// 30 days.
+pub const MAX_ETA_DELAY: i64 = 30 * 24 * 60 * 60;
+
+// Executions a `TransactionHistory` keeps.
+pub const MAX_HISTORY_ENTRIES: usize = 100;
+
+// Actions a `MultisigChangelog` keeps.
+pub const CHANGELOG_LEN: usize = 32;
+
+// Shortest inactivity period after which guardians may start a recovery, 90
+// days, the same as `COLD_RECOVERY_GRACE_PERIOD`.
+pub const MIN_RECOVERY_INACTIVITY_PERIOD: i64 = 90 * 24 * 60 * 60;
+
+// Shortest delay between starting and completing a recovery, 7 days.
+pub const MIN_RECOVERY_DELAY: i64 = 7 * 24 * 60 * 60;
+
+// Bytes of a multisig account without owners, see `Multisig::space`: the
+// discriminator, the fixed size fields, a byte per unset option and the length
+// of each list, plus the cold recovery key.
+const MULTISIG_BASE_SPACE: usize = 247;
+
+// A policy evaluated against the instructions of every new transaction.
+pub trait TransactionValidator {
+    fn validate(&self, multisig: &Multisig, instructions: &[Instruction]) -> Result<()>;
+}
+
+// Only programs listed in `allowed_programs` or `known_programs` may be
+// invoked.
+pub struct AllowlistValidator;
+
+impl TransactionValidator for AllowlistValidator {
+    fn validate(&self, multisig: &Multisig, instructions: &[Instruction]) -> Result<()> {
+        for ix in instructions.iter() {
+            if !multisig.allowed_programs.contains(&ix.program_id)
+                && !multisig
+                    .known_programs
+                    .iter()
+                    .any(|k| k.program_id == ix.program_id)
+            {
+                return Err(ErrorCode::ProgramNotAllowed.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+// System transfers may not move more than `max_transfer_lamports`.
+pub struct MaxValueValidator;
+
+impl TransactionValidator for MaxValueValidator {
+    fn validate(&self, multisig: &Multisig, instructions: &[Instruction]) -> Result<()> {
+        for ix in instructions.iter() {
+            if ix.program_id != system_program::ID {
+                continue;
+            }
+            // Bincode encoded `SystemInstruction::Transfer { lamports }`.
+            if ix.data.len() >= 12 && ix.data[..4] == 2u32.to_le_bytes() {
+                let mut lamports = [0u8; 8];
+                lamports.copy_from_slice(&ix.data[4..12]);
+                if u64::from_le_bytes(lamports) > multisig.max_transfer_lamports {
+                    return Err(ErrorCode::TransferTooLarge.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Instruction data may not exceed `MAX_INSTRUCTION_DATA_LEN` bytes.
+pub struct DataSizeLimiter;
+
+impl TransactionValidator for DataSizeLimiter {
+    fn validate(&self, _multisig: &Multisig, instructions: &[Instruction]) -> Result<()> {
+        if instructions
+            .iter()
+            .any(|ix| ix.data.len() > MAX_INSTRUCTION_DATA_LEN)
+        {
+            return Err(ErrorCode::InstructionDataTooLarge.into());
+        }
+        Ok(())
+    }
+}
+
+// A transaction may not contain more than `MAX_INSTRUCTIONS` instructions, or
+// the multisig's own `max_instructions_global` if set.
+pub struct InstructionCountLimiter;
+
+impl TransactionValidator for InstructionCountLimiter {
+    fn validate(&self, multisig: &Multisig, instructions: &[Instruction]) -> Result<()> {
+        let max = match multisig.max_instructions_global {
+            0 => MAX_INSTRUCTIONS,
+            max => max as usize,
+        };
+        if instructions.len() > max {
+            return Err(ErrorCode::TooManyInstructions.into());
+        }
+        Ok(())
+    }
+}
+
+// The multisig program itself may not be invoked. Note this also rules out
+// governance instructions, e.g., `set_validators`, so it can't be undone.
+pub struct NoSelfCpiValidator {
+    program_id: Pubkey,
+}
+
+impl TransactionValidator for NoSelfCpiValidator {
+    fn validate(&self, _multisig: &Multisig, instructions: &[Instruction]) -> Result<()> {
+        if instructions
+            .iter()
+            .any(|ix| ix.program_id == self.program_id)
+        {
+            return Err(ErrorCode::SelfCpiNotAllowed.into());
+        }
+        Ok(())
+    }
+}
+
+// Program wide circuit breaker. There is exactly one per deployment, at
+// `pda::global_pause`.
+#[account]
+pub struct GlobalPause {
+    authority: Pubkey,
+    paused: bool,
+}
+
+// The last `MAX_HISTORY_ENTRIES` executions of a multisig's transactions, at
+// `pda::history`, oldest first until full, then a ring buffer whose oldest
+// entry is at `next`.
+#[account]
+pub struct TransactionHistory {
+    multisig: Pubkey,
+    // Where the next entry goes.
+    next: u8,
+    entries: Vec<HistoryEntry>,
+}
+
+impl TransactionHistory {
+    // Discriminator, multisig, next and a full vector of entries.
+    pub const SPACE: usize = 8 + 32 + 1 + 4 + MAX_HISTORY_ENTRIES * HistoryEntry::SPACE;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug, PartialEq)]
+pub struct HistoryEntry {
+    // The transaction's `contents_hash` when it executed.
+    transaction_hash: [u8; 32],
+    executed_at: i64,
+    executor: Pubkey,
+    instruction_count: u8,
+}
+
+impl HistoryEntry {
+    pub const SPACE: usize = 32 + 8 + 32 + 1;
+}
+
+// The last `CHANGELOG_LEN` governance actions on a multisig, at
+// `pda::changelog`, so they can be read without parsing logs. A ring buffer
+// whose oldest entry is at `head`. Unused entries have action 0.
+#[account]
+pub struct MultisigChangelog {
+    // `CHANGELOG_LEN` entries.
+    entries: [ChangelogEntry; 32],
+    // Where the next entry goes.
+    head: u8,
+}
+
+impl MultisigChangelog {
+    // Discriminator, the entries with their transactions set, and head.
+    pub const SPACE: usize = 8 + CHANGELOG_LEN * ChangelogEntry::SPACE + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Debug, Default, PartialEq)]
+pub struct ChangelogEntry {
+    // One of the `CHANGE_*` actions.
+    action: u8,
+    // Who proposed, approved, rejected, vetoed, executed or became an owner.
+    actor: Pubkey,
+    timestamp: i64,
+    transaction: Option<Pubkey>,
+}
+
+impl ChangelogEntry {
+    pub const SPACE: usize = 1 + 32 + 8 + 1 + 32;
+}
+
+// Instructions of a single transaction, read in place rather than
+// deserialized with the `Transaction` that references them, so large proposals
+// don't slow down every approval.
+#[account(zero_copy)]
+#[allow(clippy::repr_packed_without_abi)]
+pub struct InstructionBuffer {
+    // The multisig the instructions are proposed to.
+    multisig: Pubkey,
+    // The owner writing the instructions.
+    proposer: Pubkey,
+    // Number of bytes of `data` written so far.
+    len: u64,
+    // Non zero once proposed, after which the buffer can't be written.
+    locked: u8,
+    // Borsh encoded `Vec<BufferedInstruction>`.
+    data: [u8; 4096],
+}
+
+impl InstructionBuffer {
+    // Decodes the written instructions.
+    fn instructions(&self) -> Result<Vec<Instruction>> {
+        let mut data = &self.data[..self.len as usize];
+        let ixs: Vec<BufferedInstruction> = AnchorDeserialize::deserialize(&mut data)
+            .map_err(|_| ErrorCode::InvalidInstructionBuffer)?;
+        Ok(ixs
+            .into_iter()
+            .map(|ix| Instruction {
+                program_id: ix.program_id,
+                accounts: ix.accounts.into_iter().map(Into::into).collect(),
+                data: ix.data,
+            })
+            .collect())
+    }
+}
+
+// An instruction as encoded in an `InstructionBuffer`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BufferedInstruction {
+    program_id: Pubkey,
+    accounts: Vec<TransactionAccount>,
+    data: Vec<u8>,
+}
+
+#[account]
+pub struct Transaction {
+    // The multisig account this transaction belongs to.
+    multisig: Pubkey,
+    // Target program to execute against.
+    program_id: Vec<Pubkey>,
+    // Accounts requried for the transaction.
+    accounts: Vec<Vec<TransactionAccount>>,
+    // Instruction data for the transaction.
+    data: Vec<Vec<u8>>,
+    // The owners that approved the transaction, in order of approval.
+    approvals: Vec<Approval>,
+    // Where the transaction is in its lifecycle. Ensures one time execution.
+    status: TransactionStatus,
+    // Execute the instructions grouped by program id instead of in the given
+    // order. This keeps consecutive CPIs to the same program together, but is
+    // only safe when instructions for different programs don't depend on each
+    // other's effects. When false, the proposed order is preserved.
+    sort_instructions_by_program: bool,
+    // The owner that created the transaction.
+    proposer: Pubkey,
+    // Proposal fee paid by the proposer.
+    fee_paid: u64,
+    // When the accounts needed for execution were last confirmed present.
+    accounts_validated_at: Option<i64>,
+    // The multisig's `owner_set_seqno` when the transaction was proposed.
+    owner_set_seqno: u32,
+    // The owners that rejected the transaction.
+    rejectors: Vec<Pubkey>,
+    // When the transaction was executed or cancelled.
+    finalized_at: Option<i64>,
+    // The account that executed the transaction.
+    executor: Option<Pubkey>,
+    // Unix timestamp from which the transaction can no longer be approved or
+    // executed.
+    expires_at: Option<i64>,
+    // When the approvals last reached the threshold, starting the multisig's
+    // timelock, unless a higher threshold the instructions need is reached
+    // later. Cleared if they drop below it again.
+    threshold_reached_at: Option<i64>,
+    // Index of the vault signing the instructions. See `pda::vault`.
+    vault_index: u8,
+    // Number of the transaction's ephemeral signers also signing the
+    // instructions. See `pda::ephemeral_signer`.
+    ephemeral_signers: u8,
+    // Index of the first instruction, in execution order, that hasn't been
+    // executed yet.
+    next_instruction: u8,
+    // The `InstructionBuffer` holding the instructions, instead of
+    // `program_id`, `accounts` and `data`.
+    instruction_buffer: Option<Pubkey>,
+    // Position of the transaction among the multisig's transactions, in order
+    // of proposal.
+    index: u64,
+    // Unix timestamp of the proposal.
+    created_at: i64,
+    // Why the transaction was proposed, at most `MAX_MEMO_LEN` bytes.
+    memo: Option<String>,
+    // Transaction that must have executed before this one may. It must not
+    // be closed before, and is given among the remaining accounts on
+    // execution.
+    depends_on: Option<Pubkey>,
+    // Changes to the multisig's config made on execution, see
+    // `propose_config_change`.
+    config_actions: Vec<ConfigAction>,
+    // Hash of the instructions of a draft created by
+    // `create_hashed_transaction`, until they are revealed.
+    instructions_hash: Option<[u8; 32]>,
+    // Owners whose approval was relayed with `approve_with_signature`.
+    signature_approvals: Vec<Pubkey>,
+    // Times the transaction may execute, once if zero.
+    max_executions: u32,
+    // Times the transaction has executed.
+    execution_count: u32,
+    // Seconds that must pass between two executions.
+    min_execution_interval: i64,
+    // Unix timestamp of the latest execution, if any.
+    last_executed_at: Option<i64>,
+    // Unix timestamps from which, and until which, the transaction may
+    // execute.
+    not_before: Option<i64>,
+    not_after: Option<i64>,
+    // Compute budget the execution should request, see
+    // `set_transaction_compute_budget`.
+    compute_unit_limit: Option<u32>,
+    compute_unit_price: Option<u64>,
+    // The transfer proposed with `propose_sol_transfer`, if any.
+    sol_transfer: Option<SolTransfer>,
+    // The transfer proposed with `propose_token_transfer`, if any.
+    token_transfer: Option<TokenTransfer>,
+    // The upgrade proposed with `propose_program_upgrade`, if any.
+    program_upgrade: Option<ProgramUpgrade>,
+    // The hand-off proposed with `propose_set_upgrade_authority`, if any.
+    authority_transfer: Option<AuthorityTransfer>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct AuthorityTransfer {
+    program: Pubkey,
+    // The new upgrade authority, or `None` to make the program immutable.
+    new_authority: Option<Pubkey>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ProgramUpgrade {
+    program: Pubkey,
+    buffer: Pubkey,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TokenTransfer {
+    // Token account of the multisig signer the tokens are moved from.
+    source: Pubkey,
+    mint: Pubkey,
+    destination: Pubkey,
+    amount: u64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct SolTransfer {
+    recipient: Pubkey,
+    // Lamports moved from the multisig signer.
+    amount: u64,
+}
+
+impl Transaction {
+    // Summed weight of the owners that approved the transaction, leaving out
+    // those who may not approve (any more).
+    fn approval_weight(&self, multisig: &Multisig) -> Result<u64> {
+        let mut weight: u64 = 0;
+        for a in self.approvals.iter() {
+            weight = weight
+                .checked_add(multisig.approval_weight_of(&a.owner))
+                .ok_or(ErrorCode::Overflow)?;
+        }
+        Ok(weight)
+    }
+
+    // When the current approvals first added up to the given weight, if they
+    // do. Approvals are kept in the order they were made, so this is the time
+    // of the approval that brought the current approvers to that weight.
+    fn weight_reached_at(&self, multisig: &Multisig, weight: u64) -> Result<Option<i64>> {
+        let mut total: u64 = 0;
+        for a in self.approvals.iter() {
+            total = total
+                .checked_add(multisig.approval_weight_of(&a.owner))
+                .ok_or(ErrorCode::Overflow)?;
+            if total >= weight {
+                return Ok(Some(a.approved_at));
+            }
+        }
+        Ok(None)
+    }
+
+    // Whether every required approver of the multisig approved the
+    // transaction.
+    fn has_required_approvals(&self, multisig: &Multisig) -> bool {
+        multisig
+            .required_approvers
+            .iter()
+            .all(|r| self.approvals.iter().any(|a| &a.owner == r))
+    }
+
+    // Whether every approval group of the multisig reached its quorum.
+    fn has_group_approvals(&self, multisig: &Multisig) -> bool {
+        multisig.approval_groups.iter().all(|g| {
+            let approvals = g
+                .members
+                .iter()
+                .filter(|m| self.approvals.iter().any(|a| &a.owner == *m))
+                .count();
+            approvals as u64 >= g.quorum
+        })
+    }
+
+    // Records an owner's approval, replacing any rejection. Approving again
+    // keeps the original approval time.
+    fn approve(&mut self, owner: &Pubkey, now: i64) {
+        self.rejectors.retain(|r| r != owner);
+        if !self.approvals.iter().any(|a| &a.owner == owner) {
+            self.approvals.push(Approval {
+                owner: *owner,
+                approved_at: now,
+            });
+        }
+    }
+
+    // Withdraws an owner's approval, if any.
+    fn revoke(&mut self, owner: &Pubkey) {
+        self.approvals.retain(|a| &a.owner != owner);
+    }
+
+    // Records an owner's rejection, withdrawing their approval.
+    fn reject(&mut self, owner: &Pubkey) {
+        self.revoke(owner);
+        if !self.rejectors.contains(owner) {
+            self.rejectors.push(*owner);
+        }
+    }
+
+    // Starts the timelock and marks the transaction approved when the
+    // approvals reach the threshold, and resets both when they drop below it.
+    fn update_threshold_reached(&mut self, multisig: &Multisig, now: i64) -> Result<()> {
+        if self.approval_weight(multisig)? < multisig.threshold
+            || !self.has_required_approvals(multisig)
+            || !self.has_group_approvals(multisig)
+        {
+            self.threshold_reached_at = None;
+            self.status = TransactionStatus::Active;
+        } else if self.threshold_reached_at.is_none() {
+            self.threshold_reached_at = Some(now);
+            self.status = TransactionStatus::Approved;
+        }
+        Ok(())
+    }
+
+    // Fails unless the transaction can still be approved, rejected or
+    // executed.
+    fn check_pending(&self) -> Result<()> {
+        match self.status {
+            TransactionStatus::Executed => Err(ErrorCode::AlreadyExecuted.into()),
+            TransactionStatus::Cancelled => Err(ErrorCode::TransactionCancelled.into()),
+            TransactionStatus::Draft => Err(ErrorCode::TransactionIsDraft.into()),
+            TransactionStatus::Expired => Err(ErrorCode::TransactionExpired.into()),
+            TransactionStatus::Active | TransactionStatus::Approved => Ok(()),
+        }
+    }
+
+    // The instructions of the transaction, read from its instruction buffer
+    // among the given accounts if it has one.
+    fn instructions(&self, accounts: &TransactionAccountMap) -> Result<Vec<Instruction>> {
+        match self.instruction_buffer {
+            Some(key) => {
+                let info = accounts.get(&key).ok_or(ErrorCode::MissingAccount)?;
+                let buffer: Loader<InstructionBuffer> = Loader::try_from(info)?;
+                let buffer = buffer.load()?;
+                buffer.instructions()
+            }
+            None => Ok(self.into()),
+        }
+    }
+
+    // The SHA-256 hash of what the transaction executes, and when: its
+    // instructions as hashed by `instructions_hash`, the instruction buffer
+    // holding them instead, if any, its config changes, and the Borsh encoded
+    // recurrence, schedule, dependency and expiry, which the proposer may
+    // still edit after an owner signed offline.
+    fn contents_hash(&self) -> Result<[u8; 32]> {
+        let encode = |e: std::io::Error| ProgramError::BorshIoError(e.to_string());
+        let instructions = instructions_hash(&self.program_id, &self.accounts, &self.data)?;
+        let buffer = self.instruction_buffer.try_to_vec().map_err(encode)?;
+        let config_actions = self.config_actions.try_to_vec().map_err(encode)?;
+        let conditions = (
+            self.max_executions,
+            self.min_execution_interval,
+            self.not_before,
+            self.not_after,
+            self.depends_on,
+            self.expires_at,
+        )
+            .try_to_vec()
+            .map_err(encode)?;
+        Ok(hash::hashv(&[&instructions, &buffer, &config_actions, &conditions]).to_bytes())
+    }
+
+    // Whether the transaction expired before it was executed.
+    fn is_expired(&self, now: i64) -> bool {
+        self.status == TransactionStatus::Expired
+            || (self.status != TransactionStatus::Executed
+                && matches!(self.expires_at, Some(expires_at) if now >= expires_at))
+    }
+
+    // Marks a pending transaction that has expired as `Expired`, returning
+    // whether it did. Callers then return successfully, since failing would
+    // roll the status back.
+    fn mark_expired(&mut self, now: i64) -> bool {
+        if self.check_pending().is_err() || !self.is_expired(now) {
+            return false;
+        }
+        msg!("Transaction expired");
+        self.status = TransactionStatus::Expired;
+        self.finalized_at = self.expires_at;
+        true
+    }
+}
+
+// An owner's approval of a transaction.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct Approval {
+    owner: Pubkey,
+    approved_at: i64,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Debug)]
+pub enum ConfigAction {
+    // Adds a pending owner, see `set_owners`.
+    AddOwner { owner: Pubkey },
+    RemoveOwner { owner: Pubkey },
+    ChangeThreshold { threshold: u64 },
+    SetTimelock { timelock_seconds: i64 },
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransactionStatus {
+    // Waiting for approvals.
+    Active,
+    // Approved by threshold owners, waiting for execution.
+    Approved,
+    // Executed by `execute_transaction`.
+    Executed,
+    // Rejected by enough owners that the threshold can't be reached.
+    Cancelled,
+    // Instructions still being added by the proposer. Not yet approvable.
+    Draft,
+    // Expired before it was executed. Only recorded once `approve`,
+    // `execute_transaction` or `execute_instruction` is called after the
+    // expiry, see `Transaction::is_expired` for any transaction.
+    Expired,
+}
+
+// One transaction of a `batch_create_transactions` call, given the same way as
+// the arguments of `create_transaction`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchTransactionParams {
+    pid: Vec<Pubkey>,
+    accs: Vec<Vec<TransactionAccount>>,
+    data: Vec<Vec<u8>>,
+    sort_instructions_by_program: bool,
+    expires_at: Option<i64>,
+    vault_index: u8,
+    ephemeral_signers: u8,
+    // Why the transaction is proposed, shown to the signers. No memo if empty.
+    description: String,
+    // Earliest unix timestamp the transaction may execute at, if any.
+    eta: Option<i64>,
+}
+
+impl From<&Transaction> for Vec<Instruction> {
+    fn from(tx: &Transaction) -> Vec<Instruction> {
+        let mut instructions: Vec<Instruction> = Vec::new();
+        for (i, _pid) in tx.program_id.iter().enumerate() {
+            instructions.push(Instruction {
+                program_id: tx.program_id[i],
+                accounts: tx.accounts[i].clone().into_iter().map(Into::into).collect(),
+                data: tx.data[i].clone(),
+            })
+        }
+        instructions
+    }
+}
+
+// Remaining accounts indexed by address, so that each instruction's account
+// list is resolved with a binary search instead of a linear scan.
+pub struct TransactionAccountMap<'a, 'info> {
+    accounts: &'a [AccountInfo<'info>],
+    // (address, index into `accounts`), sorted by address.
+    index: Vec<(Pubkey, usize)>,
+}
+
+impl<'a, 'info> TransactionAccountMap<'a, 'info> {
+    pub fn new(accounts: &'a [AccountInfo<'info>]) -> Self {
+        let mut index: Vec<(Pubkey, usize)> = accounts
+            .iter()
+            .enumerate()
+            .map(|(i, acc)| (*acc.key, i))
+            .collect();
+        index.sort_by_key(|(key, _)| *key);
+        Self { accounts, index }
+    }
+
+    pub fn get(&self, key: &Pubkey) -> Option<&'a AccountInfo<'info>> {
+        self.index
+            .binary_search_by(|(k, _)| k.cmp(key))
+            .ok()
+            .map(|i| &self.accounts[self.index[i].1])
+    }
+
+    // Account infos required to invoke the given instruction.
+    pub fn instruction_accounts(&self, ix: &Instruction) -> Result<Vec<AccountInfo<'info>>> {
+        let mut infos = Vec::with_capacity(ix.accounts.len() + 1);
+        for meta in ix.accounts.iter() {
+            infos.push(
+                self.get(&meta.pubkey)
+                    .ok_or(ErrorCode::MissingAccount)?
+                    .clone(),
+            );
+        }
+        infos.push(
+            self.get(&ix.program_id)
+                .ok_or(ErrorCode::MissingAccount)?
+                .clone(),
+        );
+        Ok(infos)
+    }
+
+    // Checks the accounts given for the given instruction grant everything
+    // its stored metadata requires: writable accounts are writable, signers
+    // sign, unless they are among the given PDAs the program signs for, and
+    // the program is executable.
+    pub fn check_instruction_accounts(
+        &self,
+        ix: &Instruction,
+        program_signers: &[Pubkey],
+    ) -> Result<()> {
+        for meta in ix.accounts.iter() {
+            let info = self.get(&meta.pubkey).ok_or(ErrorCode::MissingAccount)?;
+            if (meta.is_writable && !info.is_writable)
+                || (meta.is_signer && !info.is_signer && !program_signers.contains(&meta.pubkey))
+            {
+                msg!("Account {} does not match the transaction", meta.pubkey);
+                return Err(ErrorCode::AccountMismatch.into());
+            }
+        }
+        let program = self.get(&ix.program_id).ok_or(ErrorCode::MissingAccount)?;
+        if !program.executable {
+            msg!("Program {} is not executable", ix.program_id);
+            return Err(ErrorCode::AccountMismatch.into());
+        }
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct TransactionAccount {
+    pubkey: Pubkey,
+    is_signer: bool,
+    is_writable: bool,
+}
+
+impl From<AccountMeta> for TransactionAccount {
+    fn from(meta: AccountMeta) -> TransactionAccount {
+        TransactionAccount {
+            pubkey: meta.pubkey,
+            is_signer: meta.is_signer,
+            is_writable: meta.is_writable,
+        }
+    }
+}
+
+impl From<TransactionAccount> for AccountMeta {
+    fn from(account: TransactionAccount) -> AccountMeta {
+        match account.is_writable {
+            false => AccountMeta::new_readonly(account.pubkey, account.is_signer),
+            true => AccountMeta::new(account.pubkey, account.is_signer),
+        }
+    }
+}
+
+// Decoding of Squads v3 multisig accounts for `migrate_from_squads_v3`.
+pub mod squads_v3 {
+    use super::*;
+
+    // The Squads v3 program, SMPLecH534NA9acpos4G6x7uf3LWbCAwZQE9e8ZekMu.
+    pub const ID: Pubkey = Pubkey::new_from_array([
+        6, 126, 145, 221, 206, 113, 192, 199, 95, 63, 255, 186, 30, 214, 44, 175, 64, 4, 111, 29,
+        216, 195, 82, 136, 59, 16, 168, 125, 183, 58, 217, 48,
+    ]);
+
+    // The Squads v3 `Ms` account, following its 8 byte anchor discriminator.
+    #[derive(AnchorDeserialize)]
+    pub struct Ms {
+        pub threshold: u16,
+        pub authority_index: u16,
+        pub transaction_index: u32,
+        pub ms_change_index: u32,
+        pub bump: u8,
+        pub create_key: Pubkey,
+        pub allow_external_execute: bool,
+        pub keys: Vec<Pubkey>,
+    }
+
+    impl Ms {
+        pub fn decode(info: &AccountInfo) -> Result<Self> {
+            Ms::from_data(&info.try_borrow_data()?)
+        }
+
+        // Decodes the data of a Squads v3 `Ms` account.
+        pub fn from_data(data: &[u8]) -> Result<Self> {
+            let discriminator = &hash::hash(b"account:Ms").to_bytes()[..8];
+            if data.len() < 8 || &data[..8] != discriminator {
+                return Err(ErrorCode::InvalidSquadsMultisig.into());
+            }
+            // Accounts are allocated with room to spare, so ignore trailing
+            // bytes.
+            let mut rest = &data[8..];
+            Ms::deserialize(&mut rest).map_err(|_| ErrorCode::InvalidSquadsMultisig.into())
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // The data of an `Ms` account with two keys, as Squads v3 writes it.
+        fn ms_data() -> Vec<u8> {
+            let keys = vec![
+                Pubkey::new_from_array([1; 32]),
+                Pubkey::new_from_array([2; 32]),
+            ];
+            let fields = (
+                2u16,
+                1u16,
+                5u32,
+                3u32,
+                254u8,
+                Pubkey::new_from_array([9; 32]),
+                true,
+            );
+            let mut data = hash::hash(b"account:Ms").to_bytes()[..8].to_vec();
+            data.extend(fields.try_to_vec().unwrap());
+            data.extend(keys.try_to_vec().unwrap());
+            data
+        }
+
+        #[test]
+        fn decodes_an_ms_account() {
+            let ms = Ms::from_data(&ms_data()).unwrap();
+            assert_eq!(ms.threshold, 2);
+            assert_eq!(ms.authority_index, 1);
+            assert_eq!(ms.transaction_index, 5);
+            assert_eq!(ms.ms_change_index, 3);
+            assert_eq!(ms.bump, 254);
+            assert_eq!(ms.create_key, Pubkey::new_from_array([9; 32]));
+            assert!(ms.allow_external_execute);
+            assert_eq!(
+                ms.keys,
+                vec![
+                    Pubkey::new_from_array([1; 32]),
+                    Pubkey::new_from_array([2; 32]),
+                ]
+            );
+        }
+
+        #[test]
+        fn ignores_trailing_bytes() {
+            let mut data = ms_data();
+            data.extend_from_slice(&[0; 64]);
+            assert_eq!(Ms::from_data(&data).unwrap().keys.len(), 2);
+        }
+
+        #[test]
+        fn rejects_another_discriminator() {
+            let mut data = ms_data();
+            data[0] ^= 1;
+            assert!(Ms::from_data(&data).is_err());
+        }
+
+        #[test]
+        fn rejects_truncated_data() {
+            let data = ms_data();
+            assert!(Ms::from_data(&data[..4]).is_err());
+            assert!(Ms::from_data(&data[..data.len() - 1]).is_err());
+        }
+    }
+}
+
+// Verification of owner signatures checked by the ed25519 program, for
+// `approve_with_signature`.
+pub mod ed25519 {
+    use super::*;
+
+    // The ed25519 signature verification program,
+    // Ed25519SigVerify111111111111111111111111111.
+    pub const ID: Pubkey = Pubkey::new_from_array([
+        3, 125, 70, 214, 124, 147, 251, 190, 18, 249, 66, 143, 131, 141, 64, 255, 5, 112, 116, 73,
+        39, 244, 138, 100, 252, 202, 112, 68, 128, 0, 0, 0,
+    ]);
+
+    // The message an owner signs to approve a transaction: the multisig, the
+    // transaction and the owner, followed by the transaction's
+    // `owner_set_seqno`, little endian, its vault index and its
+    // `Transaction::contents_hash`. A signature thus can't approve different
+    // instructions proposed later at the same address, nor the same ones once
+    // the proposer changed when or how often they may execute.
+    pub fn approval_message(
+        multisig: &Pubkey,
+        transaction: &Pubkey,
+        owner: &Pubkey,
+        owner_set_seqno: u32,
+        vault_index: u8,
+        contents_hash: &[u8; 32],
+    ) -> Vec<u8> {
+        let mut message = Vec::with_capacity(133);
+        message.extend_from_slice(multisig.as_ref());
+        message.extend_from_slice(transaction.as_ref());
+        message.extend_from_slice(owner.as_ref());
+        message.extend_from_slice(&owner_set_seqno.to_le_bytes());
+        message.push(vault_index);
+        message.extend_from_slice(contents_hash);
+        message
+    }
+
+    // Fails unless the given instruction makes the ed25519 program verify a
+    // single signature of `message` by `pubkey`, with the signature, key and
+    // message all in the instruction itself.
+    pub fn verify(ix: &Instruction, pubkey: &Pubkey, message: &[u8]) -> Result<()> {
+        let data = &ix.data;
+        // A signature count and a padding byte, followed by the offsets: seven
+        // little endian u16s per signature.
+        if ix.program_id != ID || data.len() < 16 || data[0] != 1 {
+            return Err(ErrorCode::InvalidSignatureInstruction.into());
+        }
+        let offset = |i: usize| u16::from_le_bytes([data[2 + 2 * i], data[3 + 2 * i]]) as usize;
+        let (public_key_offset, message_offset, message_size) = (offset(2), offset(4), offset(5));
+        // The signature, public key and message instruction indices. `u16::MAX`
+        // refers to the ed25519 instruction itself.
+        if [offset(1), offset(3), offset(6)]
+            .iter()
+            .any(|&index| index != u16::MAX as usize)
+        {
+            return Err(ErrorCode::InvalidSignatureInstruction.into());
+        }
+        let signed_key = data.get(public_key_offset..public_key_offset + 32);
+        let signed_message = data.get(message_offset..message_offset + message_size);
+        if signed_key != Some(pubkey.as_ref()) || signed_message != Some(message) {
+            return Err(ErrorCode::InvalidSignatureInstruction.into());
+        }
+        Ok(())
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        // An ed25519 program instruction checking a signature of `message`
+        // by `pubkey`, laid out like `new_ed25519_instruction` does. The
+        // signature itself is checked by the runtime, not by `verify`.
+        fn signature_instruction(pubkey: &Pubkey, message: &[u8]) -> Instruction {
+            let (public_key_offset, signature_offset, message_offset) = (16u16, 48u16, 112u16);
+            let mut data = vec![1, 0];
+            for offset in [
+                signature_offset,
+                u16::MAX,
+                public_key_offset,
+                u16::MAX,
+                message_offset,
+                message.len() as u16,
+                u16::MAX,
+            ]
+            .iter()
+            {
+                data.extend_from_slice(&offset.to_le_bytes());
+            }
+            data.extend_from_slice(pubkey.as_ref());
+            data.extend_from_slice(&[7; 64]);
+            data.extend_from_slice(message);
+            Instruction {
+                program_id: ID,
+                accounts: Vec::new(),
+                data,
+            }
+        }
+
+        fn message(contents_hash: &[u8; 32]) -> Vec<u8> {
+            let key = Pubkey::new_from_array([1; 32]);
+            approval_message(&key, &key, &key, 0, 0, contents_hash)
+        }
+
+        #[test]
+        fn accepts_the_signed_message() {
+            let owner = Pubkey::new_from_array([2; 32]);
+            let ix = signature_instruction(&owner, &message(&[0; 32]));
+            assert!(verify(&ix, &owner, &message(&[0; 32])).is_ok());
+        }
+
+        #[test]
+        fn rejects_other_contents() {
+            let owner = Pubkey::new_from_array([2; 32]);
+            let ix = signature_instruction(&owner, &message(&[0; 32]));
+            assert!(verify(&ix, &owner, &message(&[1; 32])).is_err());
+        }
+
+        #[test]
+        fn rejects_another_signer() {
+            let owner = Pubkey::new_from_array([2; 32]);
+            let other = Pubkey::new_from_array([3; 32]);
+            let ix = signature_instruction(&other, &message(&[0; 32]));
+            assert!(verify(&ix, &owner, &message(&[0; 32])).is_err());
+        }
+
+        #[test]
+        fn rejects_other_programs() {
+            let owner = Pubkey::new_from_array([2; 32]);
+            let mut ix = signature_instruction(&owner, &message(&[0; 32]));
+            ix.program_id = system_program::ID;
+            assert!(verify(&ix, &owner, &message(&[0; 32])).is_err());
+        }
+    }
+}
+
+// Addresses of the associated token account program, which this tree doesn't
+// depend on, for `propose_token_transfer`.
+pub mod associated_token {
+    use super::*;
+
+    // The associated token account program,
+    // ATokenGPvbdGVxr1b2hvZbsiqW5xWH25efTNsLJA8knL.
+    pub const ID: Pubkey = Pubkey::new_from_array([
+        140, 151, 37, 143, 78, 36, 137, 241, 187, 61, 16, 41, 20, 142, 13, 131, 11, 90, 19, 153,
+        218, 255, 16, 132, 4, 142, 123, 216, 219, 233, 248, 89,
+    ]);
+
+    // The associated token account of the given wallet for the given mint.
+    pub fn address(wallet: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[wallet.as_ref(), spl_token::ID.as_ref(), mint.as_ref()],
+            &ID,
+        )
+        .0
+    }
+}
+
+// SPL token accounts that can be declared as `CpiAccount`s, which anchor-spl
+// provides for later versions of anchor.
+pub mod token {
+    use super::*;
+    use std::ops::Deref;
+
+    // An initialized SPL token account.
+    #[derive(Clone)]
+    pub struct TokenAccount(spl_token::state::Account);
+
+    impl AccountDeserialize for TokenAccount {
+        fn try_deserialize(buf: &mut &[u8]) -> std::result::Result<Self, ProgramError> {
+            TokenAccount::try_deserialize_unchecked(buf)
+        }
+
+        fn try_deserialize_unchecked(buf: &mut &[u8]) -> std::result::Result<Self, ProgramError> {
+            spl_token::state::Account::unpack(buf).map(TokenAccount)
+        }
+    }
+
+    impl Deref for TokenAccount {
+        type Target = spl_token::state::Account;
+
+        fn deref(&self) -> &Self::Target {
+            &self.0
+        }
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn deserializes_initialized_token_accounts() {
+            let account = spl_token::state::Account {
+                mint: Pubkey::new_from_array([1; 32]),
+                owner: Pubkey::new_from_array([2; 32]),
+                amount: 500,
+                state: spl_token::state::AccountState::Initialized,
+                ..Default::default()
+            };
+            let mut data = vec![0; spl_token::state::Account::LEN];
+            spl_token::state::Account::pack(account, &mut data).unwrap();
+            let token_account = TokenAccount::try_deserialize(&mut &data[..]).unwrap();
+            assert_eq!(token_account.mint, account.mint);
+            assert_eq!(token_account.amount, 500);
+        }
+
+        #[test]
+        fn rejects_other_accounts() {
+            let data = [0; spl_token::state::Account::LEN];
+            assert!(TokenAccount::try_deserialize(&mut &data[..]).is_err());
+            let data = [0; spl_token::state::Mint::LEN];
+            assert!(TokenAccount::try_deserialize(&mut &data[..]).is_err());
+        }
+    }
+}
+
+// Instructions of the native stake program, which solana-program doesn't
+// provide, for the `propose_stake_*` instructions.
+pub mod stake {
+    use super::*;
+    use anchor_lang::solana_program::instruction::AccountMeta;
+
+    // The stake program, Stake11111111111111111111111111111111111111.
+    pub const ID: Pubkey = Pubkey::new_from_array([
+        6, 161, 216, 23, 145, 55, 84, 42, 152, 52, 55, 189, 254, 42, 122, 178, 85, 127, 83, 92,
+        138, 120, 114, 43, 104, 164, 157, 192, 0, 0, 0, 0,
+    ]);
+
+    // The stake config account, StakeConfig11111111111111111111111111111111.
+    pub const CONFIG_ID: Pubkey = Pubkey::new_from_array([
+        6, 161, 216, 23, 165, 2, 5, 11, 104, 7, 145, 230, 206, 109, 184, 142, 30, 91, 113, 80, 246,
+        31, 198, 121, 10, 78, 180, 209, 0, 0, 0, 0,
+    ]);
+
+    // Size of a stake account.
+    pub const STAKE_ACCOUNT_LEN: usize = 200;
+
+    // Bincode encoded `StakeInstruction`: a little endian u32 variant
+    // index, followed by the variant's lamports, if any.
+    fn data(variant: u32, lamports: Option<u64>) -> Vec<u8> {
+        let mut data = variant.to_le_bytes().to_vec();
+        if let Some(lamports) = lamports {
+            data.extend_from_slice(&lamports.to_le_bytes());
+        }
+        data
+    }
+
+    pub fn delegate(stake: &Pubkey, authority: &Pubkey, vote: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(*stake, false),
+                AccountMeta::new_readonly(*vote, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(sysvar::stake_history::ID, false),
+                AccountMeta::new_readonly(CONFIG_ID, false),
+                AccountMeta::new_readonly(*authority, true),
+            ],
+            data: data(2, None),
+        }
+    }
+
+    pub fn split(
+        stake: &Pubkey,
+        authority: &Pubkey,
+        split_stake: &Pubkey,
+        lamports: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(*stake, false),
+                AccountMeta::new(*split_stake, false),
+                AccountMeta::new_readonly(*authority, true),
+            ],
+            data: data(3, Some(lamports)),
+        }
+    }
+
+    pub fn withdraw(
+        stake: &Pubkey,
+        authority: &Pubkey,
+        recipient: &Pubkey,
+        lamports: u64,
+    ) -> Instruction {
+        Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(*stake, false),
+                AccountMeta::new(*recipient, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(sysvar::stake_history::ID, false),
+                AccountMeta::new_readonly(*authority, true),
+            ],
+            data: data(4, Some(lamports)),
+        }
+    }
+
+    pub fn deactivate(stake: &Pubkey, authority: &Pubkey) -> Instruction {
+        Instruction {
+            program_id: ID,
+            accounts: vec![
+                AccountMeta::new(*stake, false),
+                AccountMeta::new_readonly(sysvar::clock::ID, false),
+                AccountMeta::new_readonly(*authority, true),
+            ],
+            data: data(5, None),
+        }
+    }
+}
+
+// Parsing of JSON encoded instructions for `create_transaction_from_json`.
+mod json {
+    use super::*;
+
+    #[cfg(feature = "json-instructions")]
+    pub fn parse_instructions(json_instructions: &str) -> Result<Vec<Instruction>> {
+        use serde::Deserialize;
+        use std::str::FromStr;
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct JsonInstruction {
+            program_id: String,
+            accounts: Vec<JsonAccount>,
+            data: String,
+        }
+
+        #[derive(Deserialize)]
+        #[serde(rename_all = "camelCase")]
+        struct JsonAccount {
+            pubkey: String,
+            is_signer: bool,
+            is_writable: bool,
+        }
+
+        let pubkey = |s: &str| Pubkey::from_str(s).map_err(|_| ErrorCode::InvalidJsonInstructions);
+        let instructions: Vec<JsonInstruction> = serde_json::from_str(json_instructions)
+            .map_err(|_| ErrorCode::InvalidJsonInstructions)?;
+        let mut ixs = Vec::with_capacity(instructions.len());
+        for ix in instructions.into_iter() {
+            let mut accounts = Vec::with_capacity(ix.accounts.len());
+            for acc in ix.accounts.into_iter() {
+                accounts.push(AccountMeta {
+                    pubkey: pubkey(&acc.pubkey)?,
+                    is_signer: acc.is_signer,
+                    is_writable: acc.is_writable,
+                });
+            }
+            ixs.push(Instruction {
+                program_id: pubkey(&ix.program_id)?,
+                accounts,
+                data: base64::decode(&ix.data).map_err(|_| ErrorCode::InvalidJsonInstructions)?,
+            });
+        }
+        Ok(ixs)
+    }
+
+    #[cfg(not(feature = "json-instructions"))]
+    pub fn parse_instructions(_json_instructions: &str) -> Result<Vec<Instruction>> {
+        Err(ErrorCode::JsonInstructionsDisabled.into())
+    }
+}
+
+// Program derived addresses used by the multisig.
+pub mod pda {
+    use super::*;
+
+    // The signer PDA controlled by the given multisig, i.e., the address that is
+    // used as the "authority" by other programs. The bump is the multisig nonce.
+    pub fn multisig_signer(multisig: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[multisig.as_ref()], program_id)
+    }
+
+    // The signer PDA of the given multisig's vault at the given index, letting
+    // one multisig keep e.g. its treasury, upgrade authority and mint authority
+    // apart. Vault 0 is the multisig signer.
+    pub fn vault(multisig: &Pubkey, index: u8, program_id: &Pubkey) -> (Pubkey, u8) {
+        if index == 0 {
+            return multisig_signer(multisig, program_id);
+        }
+        Pubkey::find_program_address(&[multisig.as_ref(), &[index]], program_id)
+    }
+
+    pub const EPHEMERAL_SIGNER_SEED: &[u8] = b"ephemeral_signer";
+
+    // An extra signer PDA of the given transaction, e.g. for an account its
+    // instructions create. Only signs while the transaction executes.
+    pub fn ephemeral_signer(transaction: &Pubkey, index: u8, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[EPHEMERAL_SIGNER_SEED, transaction.as_ref(), &[index]],
+            program_id,
+        )
+    }
+
+    pub const TRANSACTION_SEED: &[u8] = b"transaction";
+
+    // The transaction of the given multisig with the given index, if created
+    // by `create_transaction_pda`.
+    pub fn transaction(multisig: &Pubkey, index: u64, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(
+            &[TRANSACTION_SEED, multisig.as_ref(), &index.to_le_bytes()],
+            program_id,
+        )
+    }
+
+    // The program wide `GlobalPause` singleton.
+    pub fn global_pause(program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[b"global_pause"], program_id)
+    }
+
+    pub const HISTORY_SEED: &[u8] = b"history";
+
+    // The `TransactionHistory` of the given multisig.
+    pub fn history(multisig: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[multisig.as_ref(), HISTORY_SEED], program_id)
+    }
+
+    pub const CHANGELOG_SEED: &[u8] = b"changelog";
+
+    // The `MultisigChangelog` of the given multisig.
+    pub fn changelog(multisig: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[multisig.as_ref(), CHANGELOG_SEED], program_id)
+    }
+
+    pub const STATS_SEED: &[u8] = b"stats";
+
+    // The stats account of the given multisig. Nothing creates it yet, the
+    // address is reserved so that clients and future instructions agree on it.
+    pub fn stats(multisig: &Pubkey, program_id: &Pubkey) -> (Pubkey, u8) {
+        Pubkey::find_program_address(&[STATS_SEED, multisig.as_ref()], program_id)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        fn keys() -> (Pubkey, Pubkey) {
+            (
+                Pubkey::new_from_array([1; 32]),
+                Pubkey::new_from_array([2; 32]),
+            )
+        }
+
+        // Each derivation gives the same address every time, and the address
+        // the program's own checks, which use `create_program_address` with
+        // the stored bump, accept.
+        fn check(
+            derive: impl Fn() -> (Pubkey, u8),
+            seeds: &[&[u8]],
+            program_id: &Pubkey,
+        ) -> Pubkey {
+            let (address, bump) = derive();
+            assert_eq!(derive(), (address, bump));
+            let mut seeds = seeds.to_vec();
+            let bump = [bump];
+            seeds.push(&bump);
+            assert_eq!(
+                Pubkey::create_program_address(&seeds, program_id),
+                Ok(address)
+            );
+            address
+        }
+
+        #[test]
+        fn multisig_signer_matches_the_signer_seeds() {
+            let (multisig, program_id) = keys();
+            check(
+                || multisig_signer(&multisig, &program_id),
+                &[multisig.as_ref()],
+                &program_id,
+            );
+        }
+
+        #[test]
+        fn vault_zero_is_the_multisig_signer() {
+            let (multisig, program_id) = keys();
+            assert_eq!(
+                vault(&multisig, 0, &program_id),
+                multisig_signer(&multisig, &program_id)
+            );
+            let vault_one = check(
+                || vault(&multisig, 1, &program_id),
+                &[multisig.as_ref(), &[1]],
+                &program_id,
+            );
+            assert_ne!(vault_one, multisig_signer(&multisig, &program_id).0);
+        }
+
+        #[test]
+        fn ephemeral_signers_differ_by_index() {
+            let (transaction, program_id) = keys();
+            let first = check(
+                || ephemeral_signer(&transaction, 0, &program_id),
+                &[EPHEMERAL_SIGNER_SEED, transaction.as_ref(), &[0]],
+                &program_id,
+            );
+            let second = check(
+                || ephemeral_signer(&transaction, 1, &program_id),
+                &[EPHEMERAL_SIGNER_SEED, transaction.as_ref(), &[1]],
+                &program_id,
+            );
+            assert_ne!(first, second);
+        }
+
+        #[test]
+        fn transactions_differ_by_index() {
+            let (multisig, program_id) = keys();
+            let first = check(
+                || transaction(&multisig, 0, &program_id),
+                &[TRANSACTION_SEED, multisig.as_ref(), &0u64.to_le_bytes()],
+                &program_id,
+            );
+            let second = check(
+                || transaction(&multisig, 1, &program_id),
+                &[TRANSACTION_SEED, multisig.as_ref(), &1u64.to_le_bytes()],
+                &program_id,
+            );
+            assert_ne!(first, second);
+        }
+
+        #[test]
+        fn global_pause_matches_its_seeds() {
+            let (_, program_id) = keys();
+            check(
+                || global_pause(&program_id),
+                &[b"global_pause"],
+                &program_id,
+            );
+        }
+
+        #[test]
+        fn stats_matches_its_seeds() {
+            let (multisig, program_id) = keys();
+            check(
+                || stats(&multisig, &program_id),
+                &[STATS_SEED, multisig.as_ref()],
+                &program_id,
+            );
+        }
+    }
+}
+
+#[event]
+pub struct Heartbeat {
+    multisig: Pubkey,
+    timestamp: i64,
+}
+
+#[event]
+pub struct VotingPower {
+    multisig: Pubkey,
+    owner: Pubkey,
+    weight: u64,
+    total_weight: u64,
+}
+
+#[event]
+pub struct SignerInfo {
+    signer_pda: Pubkey,
+    bump: u8,
+    lamports: u64,
+    is_rent_exempt: bool,
+}
+
+#[event]
+pub struct MigratedFromSquadsV3 {
+    multisig: Pubkey,
+    squads_multisig: Pubkey,
+}
+
+#[event]
+pub struct MultisigCreated {
+    multisig: Pubkey,
+    owners: Vec<Pubkey>,
+    threshold: u64,
+}
+
+#[event]
+pub struct TransactionCreated {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    index: u64,
+    proposer: Pubkey,
+    fee: u64,
+}
+
+#[event]
+pub struct TransactionsBatchCreated {
+    multisig: Pubkey,
+    // In the order they were given.
+    transactions: Vec<Pubkey>,
+}
+
+#[event]
+pub struct ComputeBudgetChanged {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    index: u64,
+    compute_unit_limit: Option<u32>,
+    compute_unit_price: Option<u64>,
+}
+
+#[event]
+pub struct EtaUpdated {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    old_eta: Option<i64>,
+    new_eta: i64,
+}
+
+#[event]
+pub struct TransactionApproved {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    index: u64,
+    owner: Pubkey,
+}
+
+#[event]
+pub struct ApprovalRevoked {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    index: u64,
+    owner: Pubkey,
+}
+
+#[event]
+pub struct TransactionExecuted {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    index: u64,
+    executor: Pubkey,
+}
+
+#[event]
+pub struct TransactionVetoed {
+    multisig: Pubkey,
+    transaction: Pubkey,
+    index: u64,
+}
+
+#[event]
+pub struct FreezeChanged {
+    multisig: Pubkey,
+    frozen: bool,
+}
+
+#[event]
+pub struct RecoveryInitiated {
+    multisig: Pubkey,
+    owners: Vec<Pubkey>,
+    threshold: u64,
+    completes_at: i64,
+}
+
+// Emitted whenever the owners or threshold change.
+#[event]
+pub struct OwnersChanged {
+    multisig: Pubkey,
+    owners: Vec<Pubkey>,
+    threshold: u64,
+    owner_set_seqno: u32,
+}
+
+#[error]
+pub enum ErrorCode {
+    #[msg("The given owner is not part of this multisig.")]
+    InvalidOwner,
+    #[msg("Not enough owners signed this transaction.")]
+    NotEnoughSigners,
+    #[msg("Cannot delete a transaction that has been signed by an owner.")]
+    TransactionAlreadySigned,
+    #[msg("Overflow when adding.")]
+    Overflow,
+    #[msg("Cannot delete a transaction the owner did not create.")]
+    UnableToDelete,
     #[msg("The given transaction has already been executed.")]
     AlreadyExecuted,
     #[msg("Threshold must be less than or equal to the number of owners.")]
     InvalidThreshold,
     #[msg("program id account data must have same length")]
     ParamLength,
+    #[msg("An account required by the transaction was not provided.")]
+    MissingAccount,
+    #[msg("The instruction is not allowed for this known program.")]
+    UnknownInstruction,
+    #[msg("The given multisig signer does not match the multisig and nonce.")]
+    InvalidMultisigSigner,
+    #[msg("The transaction invokes a program that is not allowed.")]
+    ProgramNotAllowed,
+    #[msg("The transaction transfers more lamports than allowed.")]
+    TransferTooLarge,
+    #[msg("The transaction has instruction data larger than allowed.")]
+    InstructionDataTooLarge,
+    #[msg("The transaction has more instructions than allowed.")]
+    TooManyInstructions,
+    #[msg("The transaction may not invoke the multisig program.")]
+    SelfCpiNotAllowed,
+    #[msg("Refunded proposal fees must be paid to the multisig signer.")]
+    InvalidFeeTreasury,
+    #[msg("The given upgrade buffer is invalid.")]
+    InvalidUpgradeBuffer,
+    #[msg("The upgrade buffer does not match the committed hash.")]
+    UpgradeHashMismatch,
+    #[msg("The executor cannot afford the execution fee.")]
+    InsufficientComputeFee,
+    #[msg("The given token account is invalid.")]
+    InvalidTokenAccount,
+    #[msg("The token account is not owned by the multisig signer.")]
+    InvalidTokenAccountOwner,
+    #[msg("The token account still holds tokens.")]
+    NonZeroTokenBalance,
+    #[msg("The program was built without the json-instructions feature.")]
+    JsonInstructionsDisabled,
+    #[msg("The given JSON instructions are invalid.")]
+    InvalidJsonInstructions,
+    #[msg("The given program data account is invalid.")]
+    InvalidProgramData,
+    #[msg("The signer is not the program's upgrade authority.")]
+    InvalidUpgradeAuthority,
+    #[msg("The given global pause account is invalid.")]
+    InvalidGlobalPause,
+    #[msg("Too many transactions in the batch.")]
+    BatchTooLarge,
+    #[msg("A batch transaction account is not a new, rent exempt account of this program.")]
+    InvalidBatchTransaction,
+    #[msg("The signer is not the multisig's cold recovery key.")]
+    InvalidColdRecoveryKey,
+    #[msg("The cold recovery grace period has not passed yet.")]
+    ColdRecoveryTooEarly,
+    #[msg("The given account is not a Squads v3 multisig.")]
+    InvalidSquadsMultisig,
+    #[msg("The owners or threshold don't match the Squads v3 multisig.")]
+    SquadsMultisigMismatch,
+    #[msg("Owners length must be non zero.")]
+    InvalidOwnersLen,
+    #[msg("The given transaction has been cancelled.")]
+    TransactionCancelled,
+    #[msg("The transaction does not belong to the given multisig.")]
+    InvalidTransaction,
+    #[msg("The transaction has neither executed nor been cancelled.")]
+    TransactionNotFinalized,
+    #[msg("Only owners may close a transaction before the grace period passed.")]
+    CloseTooEarly,
+    #[msg("The destination is not where the multisig sends closed rent.")]
+    InvalidCloseDestination,
+    #[msg("The expiry must be in the future.")]
+    InvalidExpiry,
+    #[msg("The transaction has expired.")]
+    TransactionExpired,
+    #[msg("The timelock must not be negative.")]
+    InvalidTimelock,
+    #[msg("The timelock has not passed since the threshold was reached.")]
+    TimelockNotExpired,
+    #[msg("All multisigs of this program are paused.")]
+    GloballyPaused,
+    #[msg("The owner lacks the permission for this action.")]
+    MissingPermission,
+    #[msg("The spending limit period must be positive.")]
+    InvalidSpendingLimit,
+    #[msg("The owner has no spending limit for this mint.")]
+    NoSpendingLimit,
+    #[msg("The amount is larger than what is left of the spending limit.")]
+    SpendingLimitExceeded,
+    #[msg("The transaction is a draft that hasn't been finalized.")]
+    TransactionIsDraft,
+    #[msg("The instruction is not the next one to execute.")]
+    InvalidInstructionIndex,
+    #[msg("The instruction buffer is invalid or already proposed.")]
+    InvalidInstructionBuffer,
+    #[msg("The instructions don't fit in the instruction buffer.")]
+    InstructionBufferFull,
+    #[msg("The owners must be unique.")]
+    DuplicateOwner,
+    #[msg("The multisig has more owners than allowed.")]
+    TooManyOwners,
+    #[msg("The owner has already approved this transaction.")]
+    AlreadyApproved,
+    #[msg("A given account doesn't match the transaction's account metadata.")]
+    AccountMismatch,
+    #[msg("The nonce is not the canonical bump of the multisig signer.")]
+    InvalidNonce,
+    #[msg("The memo is longer than allowed.")]
+    MemoTooLong,
+    #[msg("The dependency is not another transaction of the same multisig.")]
+    InvalidDependency,
+    #[msg("The transaction this one depends on hasn't executed yet.")]
+    DependencyNotExecuted,
+    #[msg("A transaction proposed later has already executed.")]
+    StaleTransaction,
+    #[msg("A required approver has not approved the transaction.")]
+    MissingRequiredApproval,
+    #[msg("An approval group has a too long name or an unreachable quorum.")]
+    InvalidApprovalGroup,
+    #[msg("An approval group has not reached its quorum.")]
+    GroupQuorumNotReached,
+    #[msg("The signer is not the multisig's veto authority.")]
+    InvalidVetoAuthority,
+    #[msg("The multisig is frozen.")]
+    MultisigFrozen,
+    #[msg("The recovery guardians, threshold or periods are invalid.")]
+    InvalidRecoveryConfig,
+    #[msg("The owners have executed within the recovery inactivity period.")]
+    OwnersStillActive,
+    #[msg("No recovery is pending.")]
+    NoPendingRecovery,
+    #[msg("The recovery delay has not passed yet.")]
+    RecoveryDelayNotPassed,
+    #[msg("The signer is not a pending owner of the multisig.")]
+    NotPendingOwner,
+    #[msg("The data prefix of a deny rule is too long.")]
+    InvalidDenyRule,
+    #[msg("A deny rule forbids the instruction.")]
+    InstructionDenied,
+    #[msg("A config change needs at least one action.")]
+    InvalidConfigChange,
+    #[msg("The transaction was not created from an instructions hash.")]
+    NotHashedTransaction,
+    #[msg("The instructions don't match the transaction's instructions hash.")]
+    InstructionsHashMismatch,
+    #[msg("No ed25519 instruction verifies the owner's approval.")]
+    InvalidSignatureInstruction,
+    #[msg("The execution interval must not be negative.")]
+    InvalidRecurrence,
+    #[msg("The transaction executed too recently to execute again.")]
+    CooldownNotElapsed,
+    #[msg("The execution window must start before it ends.")]
+    InvalidExecutionWindow,
+    #[msg("The transaction can't execute outside its execution window.")]
+    OutsideExecutionWindow,
+    #[msg("The multisig signer is not the mint's authority.")]
+    InvalidMintAuthority,
+    #[msg("The token accounts are of different mints.")]
+    TokenMintMismatch,
+    #[msg("The multisig signer's associated token account doesn't exist.")]
+    SignerPdaAtaNotFound,
+    #[msg("The ETA must be between the timelock and the longest delay from now.")]
+    InvalidEta,
+    #[msg("The given transaction history account is invalid.")]
+    InvalidHistory,
+    #[msg("The given changelog account is invalid.")]
+    InvalidChangelog,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn multisig(owners: usize) -> Multisig {
+        Multisig {
+            owners: (0..owners as u8)
+                .map(|i| Pubkey::new_from_array([i; 32]))
+                .collect(),
+            threshold: 0,
+            nonce: 0,
+            known_programs: Vec::new(),
+            validators_enabled: 0,
+            max_transfer_lamports: 0,
+            proposal_fee_lamports: 0,
+            fee_treasury: None,
+            refund_fee_on_execute: false,
+            execution_compute_fee_lamports_per_ix: 0,
+            upgrade_buffer_hash: None,
+            cold_recovery_key: None,
+            last_executed_at: 0,
+            max_instructions_global: 0,
+            owner_set_seqno: 0,
+            close_destination: None,
+            timelock_seconds: 0,
+            owner_weights: Vec::new(),
+            owner_permissions: Vec::new(),
+            restrict_execution: false,
+            spending_limit: None,
+            transaction_count: 0,
+            executor_tip_lamports: 0,
+            sequential_execution: false,
+            next_execution_index: 0,
+            required_approvers: Vec::new(),
+            approval_groups: Vec::new(),
+            veto_authority: None,
+            frozen: false,
+            freeze_guardian: None,
+            freeze_threshold: 0,
+            guardians: Vec::new(),
+            guardian_threshold: 0,
+            recovery_inactivity_period: 0,
+            recovery_delay: 0,
+            pending_recovery: None,
+            pending_owners: Vec::new(),
+            allowed_programs: Vec::new(),
+            deny_rules: Vec::new(),
+            threshold_tiers: Vec::new(),
+            threshold_percent: 0,
+            authority_transfer_threshold: 0,
+            authority_transfer_timelock: 0,
+            pending_threshold: 0,
+            pending_tier_thresholds: Vec::new(),
+            pending_authority_transfer_threshold: 0,
+        }
+    }
+
+    #[test]
+    fn multisig_space_fits_a_new_multisig() {
+        let mut multisig = multisig(MAX_OWNERS);
+        multisig.cold_recovery_key = Some(Pubkey::new_from_array([0xff; 32]));
+        let mut data = Vec::new();
+        multisig.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), Multisig::space(MAX_OWNERS));
+    }
+
+    #[test]
+    fn threshold_for_raises_upgrade_authority_transfers() {
+        let mut multisig = multisig(3);
+        multisig.threshold = 2;
+        multisig.authority_transfer_threshold = 3;
+        let (program, signer) = (
+            Pubkey::new_from_array([1; 32]),
+            Pubkey::new_from_array([2; 32]),
+        );
+        let handoff = [
+            bpf_loader_upgradeable::set_upgrade_authority(&program, &signer, Some(&program)),
+            bpf_loader_upgradeable::set_upgrade_authority(&program, &signer, None),
+        ];
+        for ix in handoff.iter() {
+            assert_eq!(multisig.threshold_for(std::slice::from_ref(ix)), 3);
+        }
+        let transfer = system_instruction::transfer(&signer, &program, 1);
+        assert_eq!(multisig.threshold_for(&[transfer]), 2);
+    }
+
+    #[test]
+    fn changelog_space_fits_a_full_changelog() {
+        let entry = ChangelogEntry {
+            action: CHANGE_OWNER_KEY_ROTATED,
+            actor: Pubkey::new_from_array([0xff; 32]),
+            timestamp: i64::MAX,
+            transaction: Some(Pubkey::new_from_array([0xff; 32])),
+        };
+        let changelog = MultisigChangelog {
+            entries: [entry; CHANGELOG_LEN],
+            head: 0,
+        };
+        let mut data = Vec::new();
+        changelog.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), MultisigChangelog::SPACE);
+    }
+
+    #[test]
+    fn history_space_fits_a_full_history() {
+        let entry = HistoryEntry {
+            transaction_hash: [0xff; 32],
+            executed_at: i64::MAX,
+            executor: Pubkey::new_from_array([0xff; 32]),
+            instruction_count: u8::MAX,
+        };
+        let history = TransactionHistory {
+            multisig: Pubkey::default(),
+            next: 0,
+            entries: vec![entry; MAX_HISTORY_ENTRIES],
+        };
+        let mut data = Vec::new();
+        history.try_serialize(&mut data).unwrap();
+        assert_eq!(data.len(), TransactionHistory::SPACE);
+    }
+
+    fn transaction() -> Transaction {
+        Transaction {
+            multisig: Pubkey::default(),
+            program_id: Vec::new(),
+            accounts: Vec::new(),
+            data: Vec::new(),
+            approvals: Vec::new(),
+            status: TransactionStatus::Active,
+            sort_instructions_by_program: false,
+            proposer: Pubkey::default(),
+            fee_paid: 0,
+            accounts_validated_at: None,
+            owner_set_seqno: 0,
+            rejectors: Vec::new(),
+            finalized_at: None,
+            executor: None,
+            expires_at: None,
+            threshold_reached_at: None,
+            vault_index: 0,
+            ephemeral_signers: 0,
+            next_instruction: 0,
+            instruction_buffer: None,
+            index: 0,
+            created_at: 0,
+            memo: None,
+            depends_on: None,
+            config_actions: Vec::new(),
+            instructions_hash: None,
+            signature_approvals: Vec::new(),
+            max_executions: 0,
+            execution_count: 0,
+            min_execution_interval: 0,
+            last_executed_at: None,
+            not_before: None,
+            not_after: None,
+            compute_unit_limit: None,
+            compute_unit_price: None,
+            sol_transfer: None,
+            token_transfer: None,
+            program_upgrade: None,
+            authority_transfer: None,
+        }
+    }
+
+    // An offline approval covers when, and how often, the transaction may
+    // execute, not only what it executes.
+    #[test]
+    fn contents_hash_covers_the_execution_conditions() {
+        let hash = transaction().contents_hash().unwrap();
+        let edits: Vec<fn(&mut Transaction)> = vec![
+            |tx| tx.max_executions = 12,
+            |tx| tx.min_execution_interval = 60,
+            |tx| tx.not_before = Some(1),
+            |tx| tx.not_after = Some(1),
+            |tx| tx.depends_on = Some(Pubkey::new_from_array([1; 32])),
+            |tx| tx.expires_at = Some(1),
+        ];
+        for edit in edits {
+            let mut tx = transaction();
+            edit(&mut tx);
+            assert_ne!(tx.contents_hash().unwrap(), hash);
+        }
+        let mut tx = transaction();
+        tx.memo = Some("memo".to_string());
+        assert_eq!(tx.contents_hash().unwrap(), hash);
+    }
 }
@@ -0,0 +1,158 @@
+const anchor = require("@project-serum/anchor");
+const assert = require("assert");
+
+describe("example-protocol", () => {
+  // Configure the client to use the local cluster.
+  const provider = anchor.Provider.env()
+  anchor.setProvider(provider);
+
+  const multisigProgram = anchor.workspace.Multisig;
+  const program = anchor.workspace.ExampleProtocol;
+
+  it("Gates an operation behind a multisig decision", async () => {
+    const multisig = anchor.web3.Keypair.generate();
+    const [
+      multisigSigner,
+      nonce,
+    ] = await anchor.web3.PublicKey.findProgramAddress(
+      [multisig.publicKey.toBuffer()],
+      multisigProgram.programId
+    );
+    // Multisig::space(2), for the two owners below.
+    const multisigSize = 247 + 32 * 2;
+
+    const ownerA = anchor.web3.Keypair.generate();
+    const ownerB = anchor.web3.Keypair.generate();
+    const owners = [ownerA.publicKey, ownerB.publicKey];
+
+    // Create a 2 of 2 multisig.
+    const threshold = new anchor.BN(2);
+    await multisigProgram.rpc.createMultisig(owners, threshold, nonce, null, {
+      accounts: {
+        multisig: multisig.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await multisigProgram.account.multisig.createInstruction(
+          multisig,
+          multisigSize
+        ),
+      ],
+      signers: [multisig],
+    });
+
+    // Hand the protocol over to the multisig signer.
+    const protocol = anchor.web3.Keypair.generate();
+    await program.rpc.initialize(multisigSigner, {
+      accounts: {
+        protocol: protocol.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await program.account.protocol.createInstruction(protocol),
+      ],
+      signers: [protocol],
+    });
+
+    // The operation can't be called directly, since nobody holds the key
+    // of the multisig signer.
+    let gated = false;
+    try {
+      await program.rpc.setValue(new anchor.BN(1), {
+        accounts: {
+          protocol: protocol.publicKey,
+          admin: ownerA.publicKey,
+        },
+        signers: [ownerA],
+      });
+    } catch (err) {
+      gated = true;
+    }
+    assert.ok(gated);
+
+    // Propose calling the operation through the multisig.
+    const accounts = [
+      {
+        pubkey: protocol.publicKey,
+        isWritable: true,
+        isSigner: false,
+      },
+      {
+        pubkey: multisigSigner,
+        isWritable: false,
+        isSigner: true,
+      },
+    ];
+    const data = program.coder.instruction.encode("set_value", {
+      value: new anchor.BN(42),
+    });
+
+    const transaction = anchor.web3.Keypair.generate();
+    const txSize = 1000; // Big enough.
+    await multisigProgram.rpc.createTransaction(
+      [program.programId],
+      [accounts],
+      [data],
+      false,
+      null,
+      0,
+      0,
+      {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: transaction.publicKey,
+          proposer: ownerA.publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await multisigProgram.account.transaction.createInstruction(
+            transaction,
+            txSize
+          ),
+        ],
+        signers: [transaction, ownerA],
+      }
+    );
+
+    // The second approval reaches the threshold.
+    await multisigProgram.rpc.approve({
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: transaction.publicKey,
+        owner: ownerB.publicKey,
+      },
+      signers: [ownerB],
+    });
+
+    await multisigProgram.rpc.executeTransaction({
+      accounts: {
+        multisig: multisig.publicKey,
+        multisigSigner,
+        transaction: transaction.publicKey,
+        executor: ownerB.publicKey,
+      },
+      remainingAccounts: [
+        {
+          pubkey: protocol.publicKey,
+          isWritable: true,
+          isSigner: false,
+        },
+        {
+          pubkey: multisigSigner,
+          isWritable: false,
+          isSigner: false,
+        },
+        {
+          pubkey: program.programId,
+          isWritable: false,
+          isSigner: false,
+        },
+      ],
+      signers: [ownerB],
+    });
+
+    const protocolAccount = await program.account.protocol(protocol.publicKey);
+    assert.ok(protocolAccount.value.eq(new anchor.BN(42)));
+    assert.ok(protocolAccount.admin.equals(multisigSigner));
+  });
+});
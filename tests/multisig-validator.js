@@ -0,0 +1,74 @@
+const anchor = require("@project-serum/anchor");
+const assert = require("assert");
+
+describe("multisig-validator", () => {
+  // Configure the client to use the local cluster.
+  const provider = anchor.Provider.env()
+  anchor.setProvider(provider);
+
+  const multisigProgram = anchor.workspace.Multisig;
+  const program = anchor.workspace.MultisigValidator;
+
+  it("Tells real multisigs from other accounts", async () => {
+    const multisig = anchor.web3.Keypair.generate();
+    const [
+      _multisigSigner,
+      nonce,
+    ] = await anchor.web3.PublicKey.findProgramAddress(
+      [multisig.publicKey.toBuffer()],
+      multisigProgram.programId
+    );
+    // Multisig::space(2), for the two owners below.
+    const multisigSize = 247 + 32 * 2;
+
+    const ownerA = anchor.web3.Keypair.generate();
+    const ownerB = anchor.web3.Keypair.generate();
+    const owners = [ownerA.publicKey, ownerB.publicKey];
+
+    const threshold = new anchor.BN(2);
+    await multisigProgram.rpc.createMultisig(owners, threshold, nonce, null, {
+      accounts: {
+        multisig: multisig.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await multisigProgram.account.multisig.createInstruction(
+          multisig,
+          multisigSize
+        ),
+      ],
+      signers: [multisig],
+    });
+
+    await program.rpc.validateMultisig({
+      accounts: {
+        multisig: multisig.publicKey,
+        multisigProgram: multisigProgram.programId,
+      },
+    });
+
+    // An account owned by the multisig program that isn't a multisig.
+    const transaction = anchor.web3.Keypair.generate();
+    const tx = new anchor.web3.Transaction();
+    tx.add(
+      await multisigProgram.account.transaction.createInstruction(
+        transaction,
+        1000
+      )
+    );
+    await provider.send(tx, [transaction]);
+
+    let rejected = false;
+    try {
+      await program.rpc.validateMultisig({
+        accounts: {
+          multisig: transaction.publicKey,
+          multisigProgram: multisigProgram.programId,
+        },
+      });
+    } catch (err) {
+      rejected = true;
+    }
+    assert.ok(rejected);
+  });
+});
@@ -24,7 +24,8 @@ describe("multisig", () => {
       [multisig.publicKey.toBuffer()],
       program.programId
     );
-    const multisigSize = 200; // Big enough.
+    // Three owners and the close destination set below.
+    const multisigSize = multisigSpace(3) + 32;
 
     const ownerA = anchor.web3.Keypair.generate();
     const ownerB = anchor.web3.Keypair.generate();
@@ -39,7 +40,7 @@ describe("multisig", () => {
 
     //creste multisig
     const threshold = new anchor.BN(3);
-    await program.rpc.createMultisig(owners, threshold, nonce, {
+    await program.rpc.createMultisig(owners, threshold, nonce, null, {
       accounts: {
         multisig: multisig.publicKey,
         rent: anchor.web3.SYSVAR_RENT_PUBKEY,
@@ -59,6 +60,220 @@ describe("multisig", () => {
     assert.ok(multisigAccount.threshold.eq(new anchor.BN(3)));
     assert.deepStrictEqual(multisigAccount.owners, owners);
 
+    // Owners must be unique.
+    const duplicate = anchor.web3.Keypair.generate();
+    let duplicateRejected = false;
+    try {
+      await program.rpc.createMultisig(
+        [ownerA.publicKey, ownerA.publicKey],
+        new anchor.BN(1),
+        nonce,
+        null,
+        {
+          accounts: {
+            multisig: duplicate.publicKey,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          instructions: [
+            await program.account.multisig.createInstruction(
+              duplicate,
+              multisigSize
+            ),
+          ],
+          signers: [duplicate],
+        }
+      );
+    } catch (err) {
+      duplicateRejected = err.msg === "The owners must be unique.";
+    }
+    assert.ok(duplicateRejected);
+
+    // A multisig with the most owners allowed fits in exactly the space
+    // Multisig::space gives, and not in a byte less.
+    const maxOwners = [...Array(MAX_OWNERS)].map(
+      () => anchor.web3.Keypair.generate().publicKey
+    );
+    const createFull = async (size) => {
+      const full = anchor.web3.Keypair.generate();
+      const [, fullNonce] = await anchor.web3.PublicKey.findProgramAddress(
+        [full.publicKey.toBuffer()],
+        program.programId
+      );
+      await program.rpc.createMultisig(
+        maxOwners,
+        new anchor.BN(1),
+        fullNonce,
+        ownerA.publicKey,
+        {
+          accounts: {
+            multisig: full.publicKey,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          instructions: [
+            await program.account.multisig.createInstruction(full, size),
+          ],
+          signers: [full],
+        }
+      );
+      return full;
+    };
+    const full = await createFull(multisigSpace(MAX_OWNERS));
+    assert.strictEqual(
+      (await program.account.multisig(full.publicKey)).owners.length,
+      MAX_OWNERS
+    );
+    let tooSmall = false;
+    try {
+      await createFull(multisigSpace(MAX_OWNERS) - 1);
+    } catch (err) {
+      tooSmall = true;
+    }
+    assert.ok(tooSmall);
+
+    // Heartbeat against the new multisig.
+    await program.rpc.noop({
+      accounts: {
+        multisig: multisig.publicKey,
+        payer: localAccount.publicKey,
+      },
+      signers: [localAccount],
+    });
+
+    // Batches are capped at 5 transactions.
+    const emptyProposal = {
+      pid: [],
+      accs: [],
+      data: [],
+      sortInstructionsByProgram: false,
+      expiresAt: null,
+      vaultIndex: 0,
+      ephemeralSigners: 0,
+      description: "",
+      eta: null,
+    };
+    let batchTooLarge = false;
+    try {
+      await program.rpc.batchCreateTransactions(
+        Array(6).fill(emptyProposal),
+        {
+          accounts: {
+            multisig: multisig.publicKey,
+            proposer: ownerA.publicKey,
+          },
+          signers: [ownerA],
+        }
+      );
+    } catch (err) {
+      batchTooLarge = err.msg === "Too many transactions in the batch.";
+    }
+    assert.ok(batchTooLarge);
+
+    // Proposals can be built up over several calls.
+    const draft = anchor.web3.Keypair.generate();
+    await program.rpc.createDraftTransaction(false, null, 0, 0, {
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: draft.publicKey,
+        proposer: ownerA.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await program.account.transaction.createInstruction(draft, 1000),
+      ],
+      signers: [draft, ownerA],
+    });
+    for (let i = 0; i < 2; i++) {
+      await program.rpc.addInstruction(
+        anchor.web3.SystemProgram.programId,
+        [],
+        Buffer.from([i]),
+        {
+          accounts: {
+            multisig: multisig.publicKey,
+            transaction: draft.publicKey,
+            proposer: ownerA.publicKey,
+          },
+          signers: [ownerA],
+        }
+      );
+    }
+    assert.ok(
+      (await program.account.transaction(draft.publicKey)).status.draft !==
+        undefined
+    );
+    await program.rpc.finalizeTransaction({
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: draft.publicKey,
+        proposer: ownerA.publicKey,
+      },
+      signers: [ownerA],
+    });
+    const draftAccount = await program.account.transaction(draft.publicKey);
+    assert.ok(draftAccount.status.active !== undefined);
+    assert.strictEqual(draftAccount.data.length, 2);
+
+    // The proposer can take back a proposal nobody else signed.
+    const deleted = anchor.web3.Keypair.generate();
+    await program.rpc.createTransaction([], [], [], false, null, 0, 0, {
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: deleted.publicKey,
+        proposer: ownerA.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await program.account.transaction.createInstruction(deleted, 1000),
+      ],
+      signers: [deleted, ownerA],
+    });
+    await program.rpc.deleteTransaction({
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: deleted.publicKey,
+        proposer: ownerA.publicKey,
+      },
+      signers: [ownerA],
+    });
+    assert.strictEqual(
+      await provider.connection.getAccountInfo(deleted.publicKey),
+      null
+    );
+
+    // Transactions can live at addresses derived from their index, paid for
+    // by the proposer.
+    await transfer(provider, localAccount.publicKey, ownerA.publicKey, localAccount);
+    const index = (await program.account.multisig(multisig.publicKey))
+      .transactionCount;
+    const [indexed] = await anchor.web3.PublicKey.findProgramAddress(
+      [
+        Buffer.from("transaction"),
+        multisig.publicKey.toBuffer(),
+        index.toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    await program.rpc.createTransactionPda(
+      new anchor.BN(1000),
+      [],
+      [],
+      [],
+      false,
+      null,
+      0,
+      0,
+      {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: indexed,
+          proposer: ownerA.publicKey,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [ownerA],
+      }
+    );
+    assert.ok((await program.account.transaction(indexed)).index.eq(index));
+
     const accounts = [
       {
         pubkey: multisigSigner,
@@ -103,7 +318,7 @@ describe("multisig", () => {
 
 
     //create transaction 
-    await program.rpc.createTransaction([pid,pid], [accounts,accounts], [data,data2], {
+    await program.rpc.createTransaction([pid,pid], [accounts,accounts], [data,data2], false, null, 0, 0, {
       accounts: {
         multisig: multisig.publicKey,
         transaction: transaction.publicKey,
@@ -119,15 +334,28 @@ describe("multisig", () => {
     assert.deepStrictEqual(txAccount.accounts[0], accounts);
     assert.deepStrictEqual(txAccount.data[0], data);
     assert.ok(txAccount.multisig.equals(multisig.publicKey));
-    assert.deepStrictEqual(txAccount.didExecute, false);
+    assert.ok(txAccount.status.active !== undefined);
+    assert.ok(txAccount.proposer.equals(ownerA.publicKey));
 
-    // Now that we've reached the threshold, send the transactoin.
-    await program.rpc.approve({
+    // Tell the other owners what the transaction is for.
+    await program.rpc.setTransactionMemo("Pay ownerA for the audit", {
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: transaction.publicKey,
+        proposer: ownerA.publicKey,
+      },
+      signers: [ownerA],
+    });
+    assert.strictEqual(
+      (await program.account.transaction(transaction.publicKey)).memo,
+      "Pay ownerA for the audit"
+    );
+
+    // Confirm the accounts needed for execution are all given.
+    await program.rpc.validateAccountsPresent({
       accounts: {
         multisig: multisig.publicKey,
-        multisigSigner,
         transaction: transaction.publicKey,
-        owner: ownerB.publicKey,
       },
       remainingAccounts: [
         {
@@ -144,22 +372,49 @@ describe("multisig", () => {
           pubkey: anchor.web3.SystemProgram.programId,
           isWritable: false,
           isSigner: false,
-        },
-
-        {
-          pubkey: program.programId,
-          isWritable: false,
-          isSigner: false,
         }],
-      signers: [ownerB]
     });
+    assert.ok(
+      (await program.account.transaction(transaction.publicKey))
+        .accountsValidatedAt !== null
+    );
 
-    await program.rpc.approve({
+    // Reach the threshold.
+    for (const owner of [ownerB, ownerC]) {
+      await program.rpc.approve({
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: transaction.publicKey,
+          owner: owner.publicKey,
+        },
+        signers: [owner],
+      });
+    }
+
+    // Approving twice is an error rather than a silent no-op.
+    let alreadyApproved = false;
+    try {
+      await program.rpc.approve({
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: transaction.publicKey,
+          owner: ownerB.publicKey,
+        },
+        signers: [ownerB],
+      });
+    } catch (err) {
+      alreadyApproved =
+        err.msg === "The owner has already approved this transaction.";
+    }
+    assert.ok(alreadyApproved);
+
+    // Now that we've reached the threshold, send the transaction.
+    await program.rpc.executeTransaction({
       accounts: {
         multisig: multisig.publicKey,
         multisigSigner,
         transaction: transaction.publicKey,
-        owner: ownerC.publicKey,
+        executor: ownerA.publicKey,
       },
       remainingAccounts: [
         {
@@ -177,20 +432,2020 @@ describe("multisig", () => {
           isWritable: false,
           isSigner: false,
         },
-
         {
           pubkey: program.programId,
           isWritable: false,
           isSigner: false,
         }],
-      signers: [ownerC]
+      signers: [ownerA]
+    });
+    assert.ok(
+      (await program.account.transaction(transaction.publicKey)).status
+        .executed !== undefined
+    );
+
+    // Reclaim the rent of the executed transaction.
+    await program.rpc.closeTransaction({
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: transaction.publicKey,
+        closer: ownerB.publicKey,
+        destination: ownerA.publicKey,
+      },
+      signers: [ownerB],
+    });
+    assert.strictEqual(
+      await provider.connection.getAccountInfo(transaction.publicKey),
+      null
+    );
+
+    // Each instruction sees the multisig signer's balance as the previous one
+    // left it: two transfers that only fit the balance before execution fail
+    // together rather than overdrawing it.
+    const signerBalance = await provider.connection.getBalance(multisigSigner);
+    const halfBalance = Math.floor(signerBalance / 2) + 1;
+    const overdrawData = anchor.web3.SystemProgram.transfer({
+      fromPubkey: multisigSigner,
+      toPubkey: ownerA.publicKey,
+      lamports: new anchor.BN(halfBalance),
+    }).data;
+    const overdrawTx = anchor.web3.Keypair.generate();
+    await program.rpc.createTransaction(
+      [pid, pid],
+      [accounts, accounts],
+      [overdrawData, overdrawData],
+      false,
+      null,
+      0,
+      0,
+      {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: overdrawTx.publicKey,
+          proposer: ownerA.publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await program.account.transaction.createInstruction(
+            overdrawTx,
+            txSize
+          ),
+        ],
+        signers: [overdrawTx, ownerA],
+      }
+    );
+    for (const owner of [ownerB, ownerC]) {
+      await program.rpc.approve({
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: overdrawTx.publicKey,
+          owner: owner.publicKey,
+        },
+        signers: [owner],
+      });
+    }
+    let overdrawn = false;
+    try {
+      await program.rpc.executeTransaction({
+        accounts: {
+          multisig: multisig.publicKey,
+          multisigSigner,
+          transaction: overdrawTx.publicKey,
+          executor: ownerA.publicKey,
+        },
+        remainingAccounts: [
+          {
+            pubkey: multisigSigner,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: ownerA.publicKey,
+            isWritable: true,
+            isSigner: false,
+          },
+          {
+            pubkey: anchor.web3.SystemProgram.programId,
+            isWritable: false,
+            isSigner: false,
+          },
+          {
+            pubkey: program.programId,
+            isWritable: false,
+            isSigner: false,
+          },
+        ],
+        signers: [ownerA],
+      });
+    } catch (err) {
+      overdrawn = true;
+    }
+    assert.ok(overdrawn);
+    assert.strictEqual(
+      await provider.connection.getBalance(multisigSigner),
+      signerBalance
+    );
+
+    // Rotate ownerC out for ownerD through the multisig itself.
+    const newOwners = [ownerA.publicKey, ownerB.publicKey, ownerD.publicKey];
+    const setOwnersAccounts = [
+      {
+        pubkey: multisig.publicKey,
+        isWritable: true,
+        isSigner: false,
+      },
+      {
+        pubkey: multisigSigner,
+        isWritable: false,
+        isSigner: true,
+      },
+    ];
+    const setOwnersData = program.coder.instruction.encode("set_owners", {
+      owners: newOwners,
+    });
+    const setOwnersTx = anchor.web3.Keypair.generate();
+    await program.rpc.createTransaction(
+      [program.programId],
+      [setOwnersAccounts],
+      [setOwnersData],
+      false,
+      null,
+      0,
+      0,
+      {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: setOwnersTx.publicKey,
+          proposer: ownerA.publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await program.account.transaction.createInstruction(
+            setOwnersTx,
+            txSize
+          ),
+        ],
+        signers: [setOwnersTx, ownerA],
+      }
+    );
+    for (const owner of [ownerB, ownerC]) {
+      await program.rpc.approve({
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: setOwnersTx.publicKey,
+          owner: owner.publicKey,
+        },
+        signers: [owner],
+      });
+    }
+    await program.rpc.executeTransaction({
+      accounts: {
+        multisig: multisig.publicKey,
+        multisigSigner,
+        transaction: setOwnersTx.publicKey,
+        executor: ownerA.publicKey,
+      },
+      remainingAccounts: setOwnersAccounts
+        // The multisig signer is signed for by the program, not the client.
+        .map((meta) =>
+          meta.pubkey.equals(multisigSigner)
+            ? { ...meta, isSigner: false }
+            : meta
+        )
+        .concat({
+          pubkey: program.programId,
+          isWritable: false,
+          isSigner: false,
+        }),
+      signers: [ownerA],
+    });
+
+    // ownerD isn't an owner until it accepts, so the threshold is lowered to
+    // what ownerA and ownerB can reach until it does.
+    multisigAccount = await program.account.multisig(multisig.publicKey);
+    assert.ok(multisigAccount.threshold.eq(new anchor.BN(2)));
+    assert.deepStrictEqual(multisigAccount.owners, [
+      ownerA.publicKey,
+      ownerB.publicKey,
+    ]);
+    assert.deepStrictEqual(multisigAccount.pendingOwners, [ownerD.publicKey]);
+
+    await program.rpc.acceptOwnership({
+      accounts: {
+        multisig: multisig.publicKey,
+        newOwner: ownerD.publicKey,
+      },
+      signers: [ownerD],
+    });
+    multisigAccount = await program.account.multisig(multisig.publicKey);
+    assert.deepStrictEqual(multisigAccount.owners, newOwners);
+    assert.deepStrictEqual(multisigAccount.pendingOwners, []);
+    // Its acceptance restores the threshold set_owners had to lower.
+    assert.ok(multisigAccount.threshold.eq(new anchor.BN(3)));
+    assert.ok(multisigAccount.pendingThreshold.eq(new anchor.BN(0)));
+
+    // An owner can rotate their own key without a proposal.
+    const ownerE = anchor.web3.Keypair.generate();
+    await program.rpc.rotateOwnerKey({
+      accounts: {
+        multisig: multisig.publicKey,
+        owner: ownerD.publicKey,
+        newOwner: ownerE.publicKey,
+      },
+      signers: [ownerD, ownerE],
+    });
+    multisigAccount = await program.account.multisig(multisig.publicKey);
+    assert.deepStrictEqual(multisigAccount.owners, [
+      ownerA.publicKey,
+      ownerB.publicKey,
+      ownerE.publicKey,
+    ]);
+
+    // Config changes can be proposed as typed actions rather than
+    // instructions calling back into the program.
+    const configTx = anchor.web3.Keypair.generate();
+    await program.rpc.proposeConfigChange(
+      [{ changeThreshold: { threshold: new anchor.BN(3) } }],
+      null,
+      {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: configTx.publicKey,
+          proposer: ownerA.publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await program.account.transaction.createInstruction(
+            configTx,
+            txSize
+          ),
+        ],
+        signers: [configTx, ownerA],
+      }
+    );
+    await program.rpc.approve({
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: configTx.publicKey,
+        owner: ownerB.publicKey,
+      },
+      signers: [ownerB],
+    });
+    await program.rpc.approve({
+      accounts: {
+        multisig: multisig.publicKey,
+        transaction: configTx.publicKey,
+        owner: ownerE.publicKey,
+      },
+      signers: [ownerE],
+    });
+    await program.rpc.executeTransaction({
+      accounts: {
+        multisig: multisig.publicKey,
+        multisigSigner,
+        transaction: configTx.publicKey,
+        executor: ownerA.publicKey,
+      },
+      signers: [ownerA],
+    });
+    multisigAccount = await program.account.multisig(multisig.publicKey);
+    assert.ok(multisigAccount.threshold.eq(new anchor.BN(3)));
+
+    // A config change made by the first step of a step-wise execution sticks
+    // rather than being overwritten once the step returns.
+    const authRemainingAccounts = setOwnersAccounts
+      .map((meta) =>
+        meta.pubkey.equals(multisigSigner) ? { ...meta, isSigner: false } : meta
+      )
+      .concat({
+        pubkey: program.programId,
+        isWritable: false,
+        isSigner: false,
+      });
+    const stepTx = anchor.web3.Keypair.generate();
+    await program.rpc.createTransaction(
+      [program.programId, program.programId],
+      [setOwnersAccounts, setOwnersAccounts],
+      [
+        program.coder.instruction.encode("set_max_instructions", {
+          maxInstructionsGlobal: 5,
+        }),
+        program.coder.instruction.encode("set_close_destination", {
+          closeDestination: ownerA.publicKey,
+        }),
+      ],
+      false,
+      null,
+      0,
+      0,
+      {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: stepTx.publicKey,
+          proposer: ownerA.publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await program.account.transaction.createInstruction(stepTx, txSize),
+        ],
+        signers: [stepTx, ownerA],
+      }
+    );
+
+    // A relayed approval needs the owner's signature verified by an ed25519
+    // instruction right before it.
+    let unsignedRejected = false;
+    try {
+      await program.rpc.approveWithSignature(ownerB.publicKey, {
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: stepTx.publicKey,
+          instructions: new anchor.web3.PublicKey(
+            "Sysvar1nstructions1111111111111111111111111"
+          ),
+        },
+      });
+    } catch (err) {
+      unsignedRejected =
+        err.msg === "No ed25519 instruction verifies the owner's approval.";
+    }
+    assert.ok(unsignedRejected);
+
+    for (const owner of [ownerB, ownerE]) {
+      await program.rpc.approve({
+        accounts: {
+          multisig: multisig.publicKey,
+          transaction: stepTx.publicKey,
+          owner: owner.publicKey,
+        },
+        signers: [owner],
+      });
+    }
+    await program.rpc.executeInstruction(0, {
+      accounts: {
+        multisig: multisig.publicKey,
+        multisigSigner,
+        transaction: stepTx.publicKey,
+        executor: ownerA.publicKey,
+      },
+      remainingAccounts: authRemainingAccounts,
+      signers: [ownerA],
+    });
+    multisigAccount = await program.account.multisig(multisig.publicKey);
+    assert.strictEqual(multisigAccount.maxInstructionsGlobal, 5);
+    assert.strictEqual(multisigAccount.closeDestination, null);
+
+    await program.rpc.executeInstruction(1, {
+      accounts: {
+        multisig: multisig.publicKey,
+        multisigSigner,
+        transaction: stepTx.publicKey,
+        executor: ownerA.publicKey,
+      },
+      remainingAccounts: authRemainingAccounts,
+      signers: [ownerA],
+    });
+    multisigAccount = await program.account.multisig(multisig.publicKey);
+    assert.strictEqual(multisigAccount.maxInstructionsGlobal, 5);
+    assert.ok(multisigAccount.closeDestination.equals(ownerA.publicKey));
+    assert.ok(
+      (await program.account.transaction(stepTx.publicKey)).status.executed !==
+        undefined
+    );
+  });
+
+  it("Keeps config changes made earlier in a batch", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    await fund(provider, ms.multisigSigner, 1000000000);
+
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+    const transferTx = await propose(program, ms, owners[0], [
+      anchor.web3.SystemProgram.transfer({
+        fromPubkey: ms.multisigSigner,
+        toPubkey: recipient,
+        lamports: 1000000,
+      }),
+    ]);
+    await approve(program, ms, transferTx, [owners[1]]);
+    // Past the timelock the config change sets before the transfer runs.
+    await sleep(2000);
+    const configTx = await proposeConfig(program, ms, owners[0], [
+      { setTimelock: { timelockSeconds: new anchor.BN(1) } },
+    ]);
+    await approve(program, ms, configTx, [owners[1]]);
+
+    await program.rpc.executeBatch(2, {
+      accounts: {
+        multisig: ms.multisig,
+        multisigSigner: ms.multisigSigner,
+        executor: owners[0].publicKey,
+      },
+      remainingAccounts: [configTx, transferTx]
+        .map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }))
+        .concat(
+          executionAccounts(program, ms, [
+            anchor.web3.SystemProgram.transfer({
+              fromPubkey: ms.multisigSigner,
+              toPubkey: recipient,
+              lamports: 1000000,
+            }),
+          ])
+        ),
+      signers: [owners[0]],
+    });
+
+    const multisigAccount = await program.account.multisig(ms.multisig);
+    assert.ok(multisigAccount.timelockSeconds.eq(new anchor.BN(1)));
+    assert.ok(multisigAccount.nextExecutionIndex.eq(new anchor.BN(2)));
+    for (const tx of [configTx, transferTx]) {
+      assert.ok(
+        (await program.account.transaction(tx)).status.executed !== undefined
+      );
+    }
+    assert.strictEqual(await provider.connection.getBalance(recipient), 1000000);
+  });
+
+  it("Counts only approvals of owners allowed to approve", async () => {
+    const [ownerA, ownerB, proposer] = [newOwner(), newOwner(), newOwner()];
+    const ms = await createMultisig(program, [ownerA, ownerB, proposer], 2);
+    const ix = authIx(program, ms, "set_owner_permissions", {
+      ownerPermissions: [{ owner: proposer.publicKey, permissions: 1 }],
+      restrictExecution: false,
+    });
+    const permissionsTx = await propose(program, ms, ownerA, [ix]);
+    await approve(program, ms, permissionsTx, [ownerB]);
+    await execute(program, ms, permissionsTx, ownerA, [ix]);
+
+    // Proposing doesn't approve for an owner that may only propose.
+    const ix2 = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, proposer, [ix2]);
+    assert.deepStrictEqual(
+      (await program.account.transaction(tx)).approvals,
+      []
+    );
+    await approve(program, ms, tx, [ownerA]);
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, ownerA, [ix2]),
+        "Not enough owners signed this transaction."
+      )
+    );
+    assert.ok(
+      await fails(
+        approve(program, ms, tx, [proposer]),
+        "The owner lacks the permission for this action."
+      )
+    );
+    await approve(program, ms, tx, [ownerB]);
+    await execute(program, ms, tx, ownerA, [ix2]);
+    assert.strictEqual(
+      (await program.account.multisig(ms.multisig)).maxInstructionsGlobal,
+      5
+    );
+  });
+
+  it("Restores the thresholds lowered for pending owners", async () => {
+    const [ownerA, ownerB, ownerC, ownerD] = [
+      newOwner(),
+      newOwner(),
+      newOwner(),
+      newOwner(),
+    ];
+    const ms = await createMultisig(program, [ownerA, ownerB, ownerC], 2);
+    const policyIxs = [
+      authIx(program, ms, "set_threshold_tiers", {
+        thresholdTiers: [
+          {
+            programId: anchor.web3.SystemProgram.programId,
+            dataPrefix: Buffer.from([]),
+            threshold: new anchor.BN(3),
+          },
+        ],
+      }),
+      authIx(program, ms, "set_authority_transfer_policy", {
+        authorityTransferThreshold: new anchor.BN(3),
+        authorityTransferTimelock: new anchor.BN(0),
+      }),
+    ];
+    const policyTx = await propose(program, ms, ownerA, policyIxs);
+    await approve(program, ms, policyTx, [ownerB]);
+    await execute(program, ms, policyTx, ownerA, policyIxs);
+
+    // Replacing ownerC with ownerD lowers every threshold to what ownerA and
+    // ownerB reach until ownerD accepts.
+    const setOwnersIx = authIx(program, ms, "set_owners", {
+      owners: [ownerA.publicKey, ownerB.publicKey, ownerD.publicKey],
+    });
+    const setOwnersTx = await propose(program, ms, ownerA, [setOwnersIx]);
+    await approve(program, ms, setOwnersTx, [ownerB]);
+    await execute(program, ms, setOwnersTx, ownerA, [setOwnersIx]);
+    let multisigAccount = await program.account.multisig(ms.multisig);
+    assert.ok(multisigAccount.thresholdTiers[0].threshold.eq(new anchor.BN(2)));
+    assert.ok(multisigAccount.authorityTransferThreshold.eq(new anchor.BN(2)));
+
+    await program.rpc.acceptOwnership({
+      accounts: {
+        multisig: ms.multisig,
+        newOwner: ownerD.publicKey,
+      },
+      signers: [ownerD],
+    });
+    multisigAccount = await program.account.multisig(ms.multisig);
+    assert.ok(multisigAccount.threshold.eq(new anchor.BN(2)));
+    assert.ok(multisigAccount.thresholdTiers[0].threshold.eq(new anchor.BN(3)));
+    assert.ok(multisigAccount.authorityTransferThreshold.eq(new anchor.BN(3)));
+    assert.deepStrictEqual(multisigAccount.pendingTierThresholds, []);
+    assert.ok(
+      multisigAccount.pendingAuthorityTransferThreshold.eq(new anchor.BN(0))
+    );
+  });
+
+  it("Proposes several transactions at once", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const eta = Math.floor(Date.now() / 1000) + 3600;
+    const transactions = [
+      anchor.web3.Keypair.generate(),
+      anchor.web3.Keypair.generate(),
+    ];
+    const proposal = (description, eta) => ({
+      pid: [],
+      accs: [],
+      data: [],
+      sortInstructionsByProgram: false,
+      expiresAt: null,
+      vaultIndex: 0,
+      ephemeralSigners: 0,
+      description,
+      eta,
+    });
+    await program.rpc.batchCreateTransactions(
+      [proposal("Raise the fee", new anchor.BN(eta)), proposal("", null)],
+      {
+        accounts: {
+          multisig: ms.multisig,
+          proposer: owners[0].publicKey,
+        },
+        remainingAccounts: transactions.map((tx) => ({
+          pubkey: tx.publicKey,
+          isWritable: true,
+          isSigner: false,
+        })),
+        instructions: await Promise.all(
+          transactions.map((tx) =>
+            program.account.transaction.createInstruction(tx, TX_SIZE)
+          )
+        ),
+        signers: [owners[0], ...transactions],
+      }
+    );
+    const [first, second] = await Promise.all(
+      transactions.map((tx) => program.account.transaction(tx.publicKey))
+    );
+    assert.strictEqual(first.memo, "Raise the fee");
+    assert.ok(first.notBefore.eq(new anchor.BN(eta)));
+    assert.strictEqual(second.memo, null);
+    assert.strictEqual(second.notBefore, null);
+    assert.ok(first.index.eq(new anchor.BN(0)));
+    assert.ok(second.index.eq(new anchor.BN(1)));
+  });
+
+  it("Moves the ETA of a transaction within the limits", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    const setEta = (eta, proposer) =>
+      program.rpc.setTransactionEta(new anchor.BN(eta), {
+        accounts: {
+          multisig: ms.multisig,
+          transaction: tx,
+          proposer: proposer.publicKey,
+        },
+        signers: [proposer],
+      });
+    const now = Math.floor(Date.now() / 1000);
+
+    const eta = now + 3600;
+    await setEta(eta, owners[0]);
+    assert.ok(
+      (await program.account.transaction(tx)).notBefore.eq(new anchor.BN(eta))
+    );
+    assert.ok(
+      await fails(
+        setEta(now + 31 * 24 * 60 * 60, owners[0]),
+        "The ETA must be between the timelock and the longest delay from now."
+      )
+    );
+    assert.ok(await fails(setEta(eta, owners[1])));
+
+    await approve(program, ms, tx, [owners[1]]);
+    assert.ok(
+      await fails(
+        setEta(now + 7200, owners[0]),
+        "Cannot delete a transaction that has been signed by an owner."
+      )
+    );
+  });
+
+  it("Records executions in the transaction history", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const [history] = await anchor.web3.PublicKey.findProgramAddress(
+      [ms.multisig.toBuffer(), Buffer.from("history")],
+      program.programId
+    );
+    await program.rpc.initTransactionHistory({
+      accounts: {
+        multisig: ms.multisig,
+        history,
+        payer: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
+    });
+
+    const ix = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tx, [owners[1]]);
+    await program.rpc.executeTransaction({
+      accounts: {
+        multisig: ms.multisig,
+        multisigSigner: ms.multisigSigner,
+        transaction: tx,
+        executor: owners[0].publicKey,
+      },
+      remainingAccounts: executionAccounts(program, ms, [ix]).concat([
+        { pubkey: history, isWritable: true, isSigner: false },
+      ]),
+      signers: [owners[0]],
+    });
+
+    const historyAccount = await program.account.transactionHistory(history);
+    assert.ok(historyAccount.multisig.equals(ms.multisig));
+    assert.strictEqual(historyAccount.next, 1);
+    assert.strictEqual(historyAccount.entries.length, 1);
+    assert.ok(historyAccount.entries[0].executor.equals(owners[0].publicKey));
+    assert.strictEqual(historyAccount.entries[0].instructionCount, 1);
+
+    // Only the multisig's own history address is accepted.
+    assert.ok(
+      await fails(
+        program.rpc.initTransactionHistory({
+          accounts: {
+            multisig: ms.multisig,
+            history: anchor.web3.Keypair.generate().publicKey,
+            payer: provider.wallet.publicKey,
+            systemProgram: anchor.web3.SystemProgram.programId,
+          },
+        }),
+        "The given transaction history account is invalid."
+      )
+    );
+  });
+
+  it("Records governance actions in the changelog", async () => {
+    const owners = [newOwner(), newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const [changelog] = await anchor.web3.PublicKey.findProgramAddress(
+      [ms.multisig.toBuffer(), Buffer.from("changelog")],
+      program.programId
+    );
+    await program.rpc.initChangelog({
+      accounts: {
+        multisig: ms.multisig,
+        changelog,
+        payer: provider.wallet.publicKey,
+        systemProgram: anchor.web3.SystemProgram.programId,
+      },
     });
 
+    const ix = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    for (const [name, owner] of [
+      ["approve", owners[1]],
+      ["reject", owners[2]],
+    ]) {
+      await program.rpc[name]({
+        accounts: {
+          multisig: ms.multisig,
+          transaction: tx,
+          owner: owner.publicKey,
+        },
+        remainingAccounts: [
+          { pubkey: changelog, isWritable: true, isSigner: false },
+        ],
+        signers: [owner],
+      });
+    }
+
+    // The proposal wasn't given the changelog, so it isn't recorded.
+    const changelogAccount = await program.account.multisigChangelog(changelog);
+    assert.strictEqual(changelogAccount.head, 2);
+    const [approved, rejected, unused] = changelogAccount.entries;
+    assert.strictEqual(approved.action, 2);
+    assert.ok(approved.actor.equals(owners[1].publicKey));
+    assert.ok(approved.transaction.equals(tx));
+    assert.strictEqual(rejected.action, 3);
+    assert.ok(rejected.actor.equals(owners[2].publicKey));
+    assert.strictEqual(unused.action, 0);
+  });
+
+  it("Reports the voting power of an owner", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_owner_weights", {
+      ownerWeights: [{ owner: owners[0].publicKey, weight: new anchor.BN(3) }],
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix]);
+
+    const votingPower = async (owner) =>
+      (
+        await program.simulate.computeEffectiveVotingPower(owner, {
+          accounts: { multisig: ms.multisig },
+        })
+      ).events[0].data;
+    const power = await votingPower(owners[0].publicKey);
+    assert.ok(power.owner.equals(owners[0].publicKey));
+    assert.ok(power.weight.eq(new anchor.BN(3)));
+    assert.ok(power.totalWeight.eq(new anchor.BN(4)));
+    assert.ok((await votingPower(owners[1].publicKey)).weight.eq(new anchor.BN(1)));
+    assert.ok(await fails(votingPower(newOwner().publicKey)));
+  });
+
+  it("Spends lamports within the spending limit", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_spending_limit", {
+      owner: owners[0].publicKey,
+      mint: null,
+      amount: new anchor.BN(1000000),
+      period: new anchor.BN(24 * 60 * 60),
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix]);
+    await fund(provider, ms.multisigSigner, 10000000);
+
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+    const spend = (owner, amount) =>
+      program.rpc.spendLamports(new anchor.BN(amount), {
+        accounts: {
+          multisig: ms.multisig,
+          multisigSigner: ms.multisigSigner,
+          owner: owner.publicKey,
+          destination: recipient,
+          systemProgram: anchor.web3.SystemProgram.programId,
+        },
+        signers: [owner],
+      });
+    await spend(owners[0], 600000);
+    assert.strictEqual(await provider.connection.getBalance(recipient), 600000);
+    assert.ok(
+      (
+        await program.account.multisig(ms.multisig)
+      ).spendingLimit.spent.eq(new anchor.BN(600000))
+    );
+
+    assert.ok(
+      await fails(
+        spend(owners[0], 600000),
+        "The amount is larger than what is left of the spending limit."
+      )
+    );
+    assert.ok(
+      await fails(
+        spend(owners[1], 1),
+        "The owner has no spending limit for this mint."
+      )
+    );
+    assert.strictEqual(await provider.connection.getBalance(recipient), 600000);
+  });
+
+  it("Freezes the multisig until the owners unfreeze it", async () => {
+    const owners = [newOwner(), newOwner(), newOwner()];
+    const guardian = newOwner();
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_freeze_config", {
+      freezeGuardian: guardian.publicKey,
+      freezeThreshold: new anchor.BN(0),
+    });
+    const configTx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, configTx, [owners[1]]);
+    await execute(program, ms, configTx, owners[0], [ix]);
+
+    const ix2 = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, owners[0], [ix2]);
+    const signing = (keypairs) =>
+      keypairs.map((k) => ({
+        pubkey: k.publicKey,
+        isWritable: false,
+        isSigner: true,
+      }));
+    // An owner alone may not freeze.
+    assert.ok(
+      await fails(
+        program.rpc.freeze({
+          accounts: { multisig: ms.multisig },
+          remainingAccounts: signing([owners[0]]),
+          signers: [owners[0]],
+        }),
+        "Not enough owners signed this transaction."
+      )
+    );
+    await program.rpc.freeze({
+      accounts: { multisig: ms.multisig },
+      remainingAccounts: signing([guardian]),
+      signers: [guardian],
+    });
+    assert.ok((await program.account.multisig(ms.multisig)).frozen);
+
+    assert.ok(
+      await fails(
+        propose(program, ms, owners[0], [ix2]),
+        "The multisig is frozen."
+      )
+    );
+    assert.ok(
+      await fails(approve(program, ms, tx, [owners[1]]), "The multisig is frozen.")
+    );
+
+    // Unfreezing takes the threshold.
+    const unfreeze = (keypairs) =>
+      program.rpc.unfreeze({
+        accounts: { multisig: ms.multisig },
+        remainingAccounts: signing(keypairs),
+        signers: keypairs,
+      });
+    assert.ok(
+      await fails(
+        unfreeze([owners[0]]),
+        "Not enough owners signed this transaction."
+      )
+    );
+    await unfreeze([owners[0], owners[1]]);
+    assert.ok(!(await program.account.multisig(ms.multisig)).frozen);
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix2]);
+  });
+
+  it("Lets guardians recover only inactive multisigs", async () => {
+    const owners = [newOwner(), newOwner()];
+    const guardians = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const day = 24 * 60 * 60;
+    const recoveryIx = (inactivityPeriod, delay) =>
+      authIx(program, ms, "set_recovery_config", {
+        guardians: guardians.map((g) => g.publicKey),
+        guardianThreshold: new anchor.BN(2),
+        recoveryInactivityPeriod: new anchor.BN(inactivityPeriod),
+        recoveryDelay: new anchor.BN(delay),
+      });
+
+    // Periods shorter than the minimums are refused.
+    const shortIx = recoveryIx(day, day);
+    const shortTx = await propose(program, ms, owners[0], [shortIx]);
+    await approve(program, ms, shortTx, [owners[1]]);
+    assert.ok(
+      await fails(
+        execute(program, ms, shortTx, owners[0], [shortIx]),
+        "The recovery guardians, threshold or periods are invalid."
+      )
+    );
+
+    const ix = recoveryIx(90 * day, 7 * day);
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix]);
+    const multisigAccount = await program.account.multisig(ms.multisig);
+    assert.strictEqual(multisigAccount.guardians.length, 2);
+
+    // The owners just executed, so they aren't inactive.
+    const newOwners = [newOwner().publicKey];
+    assert.ok(
+      await fails(
+        program.rpc.initiateRecovery(newOwners, new anchor.BN(1), {
+          accounts: { multisig: ms.multisig },
+          remainingAccounts: guardians.map((g) => ({
+            pubkey: g.publicKey,
+            isWritable: false,
+            isSigner: true,
+          })),
+          signers: guardians,
+        }),
+        "The owners have executed within the recovery inactivity period."
+      )
+    );
+    assert.ok(
+      await fails(
+        program.rpc.completeRecovery({
+          accounts: { multisig: ms.multisig },
+        }),
+        "No recovery is pending."
+      )
+    );
+  });
+
+  it("Mints and freezes tokens of a mint the multisig controls", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const mint = await createMint(provider, ms.multisigSigner, ms.multisigSigner);
+    const tokenAccount = await createTokenAccount(
+      provider,
+      mint,
+      provider.wallet.publicKey
+    );
+    const proposeWith = async (name, args, mint) => {
+      const transaction = anchor.web3.Keypair.generate();
+      await program.rpc[name](...args, null, {
+        accounts: {
+          multisig: ms.multisig,
+          transaction: transaction.publicKey,
+          mint,
+          proposer: owners[0].publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await program.account.transaction.createInstruction(
+            transaction,
+            TX_SIZE
+          ),
+        ],
+        signers: [transaction, owners[0]],
+      });
+      return transaction.publicKey;
+    };
+
+    const mintTx = await proposeWith(
+      "proposeMintTo",
+      [tokenAccount, new anchor.BN(500)],
+      mint
+    );
+    const mintIx = {
+      programId: TOKEN_PROGRAM_ID,
+      keys: [
+        { pubkey: mint, isWritable: true, isSigner: false },
+        { pubkey: tokenAccount, isWritable: true, isSigner: false },
+        { pubkey: ms.multisigSigner, isWritable: false, isSigner: true },
+      ],
+    };
+    await approve(program, ms, mintTx, [owners[1]]);
+    await execute(program, ms, mintTx, owners[0], [mintIx]);
+    let data = (await provider.connection.getAccountInfo(tokenAccount)).data;
+    assert.ok(new anchor.BN(data.slice(64, 72), "le").eq(new anchor.BN(500)));
+
+    const freezeTx = await proposeWith(
+      "proposeFreezeTokenAccount",
+      [tokenAccount],
+      mint
+    );
+    const freezeIx = {
+      programId: TOKEN_PROGRAM_ID,
+      keys: [
+        { pubkey: tokenAccount, isWritable: true, isSigner: false },
+        { pubkey: mint, isWritable: false, isSigner: false },
+        { pubkey: ms.multisigSigner, isWritable: false, isSigner: true },
+      ],
+    };
+    await approve(program, ms, freezeTx, [owners[1]]);
+    await execute(program, ms, freezeTx, owners[0], [freezeIx]);
+    data = (await provider.connection.getAccountInfo(tokenAccount)).data;
+    // AccountState::Frozen.
+    assert.strictEqual(data[108], 2);
+
+    // A mint the multisig doesn't control is refused.
+    const otherMint = await createMint(
+      provider,
+      provider.wallet.publicKey,
+      provider.wallet.publicKey
+    );
+    assert.ok(
+      await fails(
+        proposeWith("proposeMintTo", [tokenAccount, new anchor.BN(1)], otherMint),
+        "The multisig signer is not the mint's authority."
+      )
+    );
+  });
+
+  it("Refuses to hand off an upgrade authority the multisig lacks", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    // Whoever deployed this program holds its upgrade authority, if any.
+    const [programData] = await anchor.web3.PublicKey.findProgramAddress(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_ID
+    );
+    const transaction = anchor.web3.Keypair.generate();
+    assert.ok(
+      await fails(
+        program.rpc.proposeSetUpgradeAuthority(
+          program.programId,
+          owners[0].publicKey,
+          null,
+          {
+            accounts: {
+              multisig: ms.multisig,
+              transaction: transaction.publicKey,
+              programData,
+              proposer: owners[0].publicKey,
+              rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+            },
+            instructions: [
+              await program.account.transaction.createInstruction(
+                transaction,
+                TX_SIZE
+              ),
+            ],
+            signers: [transaction, owners[0]],
+          }
+        ),
+        NOT_UPGRADE_AUTHORITY
+      )
+    );
+  });
+
+  it("Withdraws from a stake account the multisig controls", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const createStake = async (authority) => {
+      const stake = anchor.web3.Keypair.generate();
+      const tx = anchor.web3.StakeProgram.createAccount({
+        fromPubkey: provider.wallet.publicKey,
+        stakePubkey: stake.publicKey,
+        authorized: new anchor.web3.Authorized(authority, authority),
+        lamports:
+          (await provider.connection.getMinimumBalanceForRentExemption(200)) +
+          10000000,
+      });
+      await provider.send(tx, [stake]);
+      return stake.publicKey;
+    };
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+    const withdraw = async (stake) => {
+      const transaction = anchor.web3.Keypair.generate();
+      await program.rpc.proposeStakeWithdraw(
+        stake,
+        recipient,
+        new anchor.BN(1000000),
+        null,
+        {
+          accounts: {
+            multisig: ms.multisig,
+            transaction: transaction.publicKey,
+            proposer: owners[0].publicKey,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          instructions: [
+            await program.account.transaction.createInstruction(
+              transaction,
+              TX_SIZE
+            ),
+          ],
+          signers: [transaction, owners[0]],
+        }
+      );
+      const ix = {
+        programId: anchor.web3.StakeProgram.programId,
+        keys: [
+          { pubkey: stake, isWritable: true, isSigner: false },
+          { pubkey: recipient, isWritable: true, isSigner: false },
+          {
+            pubkey: anchor.web3.SYSVAR_CLOCK_PUBKEY,
+            isWritable: false,
+            isSigner: false,
+          },
+          {
+            pubkey: anchor.web3.SYSVAR_STAKE_HISTORY_PUBKEY,
+            isWritable: false,
+            isSigner: false,
+          },
+          { pubkey: ms.multisigSigner, isWritable: false, isSigner: true },
+        ],
+      };
+      await approve(program, ms, transaction.publicKey, [owners[1]]);
+      await execute(program, ms, transaction.publicKey, owners[0], [ix]);
+    };
+
+    await withdraw(await createStake(ms.multisigSigner));
+    assert.strictEqual(await provider.connection.getBalance(recipient), 1000000);
+
+    // The stake program refuses a stake account of another authority.
+    assert.ok(await fails(withdraw(await createStake(provider.wallet.publicKey))));
+    assert.strictEqual(await provider.connection.getBalance(recipient), 1000000);
+  });
+
+  it("Checks proposals against the enabled validators", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    // VALIDATOR_MAX_VALUE.
+    const ix = authIx(program, ms, "set_validators", {
+      validatorsEnabled: 1 << 1,
+      maxTransferLamports: new anchor.BN(1000000),
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix]);
+
+    const transferIx = (lamports) =>
+      anchor.web3.SystemProgram.transfer({
+        fromPubkey: ms.multisigSigner,
+        toPubkey: owners[0].publicKey,
+        lamports,
+      });
+    await propose(program, ms, owners[0], [transferIx(1000000)]);
+    assert.ok(
+      await fails(
+        propose(program, ms, owners[0], [transferIx(1000001)]),
+        "The transaction transfers more lamports than allowed."
+      )
+    );
+  });
+
+  it("Charges the proposal fee and refunds it on execution", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const fee = 1000000;
+    const ix = authIx(program, ms, "set_proposal_fee", {
+      proposalFeeLamports: new anchor.BN(fee),
+      feeTreasury: ms.multisigSigner,
+      refundFeeOnExecute: true,
+    });
+    const feeTx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, feeTx, [owners[1]]);
+    await execute(program, ms, feeTx, owners[0], [ix]);
+
+    const proposer = owners[0];
+    await fund(provider, proposer.publicKey, 10000000);
+    const feeAccounts = [ms.multisigSigner, anchor.web3.SystemProgram.programId]
+      .map((pubkey) => ({ pubkey, isWritable: true, isSigner: false }));
+    feeAccounts[1].isWritable = false;
+    const ix2 = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const createTransaction = async (remainingAccounts) => {
+      const transaction = anchor.web3.Keypair.generate();
+      await program.rpc.createTransaction(
+        [ix2.programId],
+        [ix2.keys],
+        [ix2.data],
+        false,
+        null,
+        0,
+        0,
+        {
+          accounts: {
+            multisig: ms.multisig,
+            transaction: transaction.publicKey,
+            proposer: proposer.publicKey,
+            rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+          },
+          remainingAccounts,
+          instructions: [
+            await program.account.transaction.createInstruction(
+              transaction,
+              TX_SIZE
+            ),
+          ],
+          signers: [transaction, proposer],
+        }
+      );
+      return transaction.publicKey;
+    };
+    assert.ok(
+      await fails(
+        createTransaction([]),
+        "An account required by the transaction was not provided."
+      )
+    );
+
+    const treasuryBefore = await provider.connection.getBalance(
+      ms.multisigSigner
+    );
+    const tx = await createTransaction(feeAccounts);
+    assert.strictEqual(
+      await provider.connection.getBalance(ms.multisigSigner),
+      treasuryBefore + fee
+    );
+    assert.ok((await program.account.transaction(tx)).feePaid.eq(new anchor.BN(fee)));
+
+    await approve(program, ms, tx, [owners[1]]);
+    const proposerBefore = await provider.connection.getBalance(
+      proposer.publicKey
+    );
+    await program.rpc.executeTransaction({
+      accounts: {
+        multisig: ms.multisig,
+        multisigSigner: ms.multisigSigner,
+        transaction: tx,
+        executor: owners[1].publicKey,
+      },
+      remainingAccounts: executionAccounts(program, ms, [
+        ix2,
+        // The refund.
+        anchor.web3.SystemProgram.transfer({
+          fromPubkey: ms.multisigSigner,
+          toPubkey: proposer.publicKey,
+          lamports: fee,
+        }),
+      ]),
+      signers: [owners[1]],
+    });
+    assert.strictEqual(
+      await provider.connection.getBalance(proposer.publicKey),
+      proposerBefore + fee
+    );
+  });
+
+  it("Upgrades itself only from the committed buffer", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    // Any account past the buffer header is hashed, so a zeroed one will do
+    // to reach the checks.
+    const buffer = anchor.web3.Keypair.generate();
+    const space = 37 + 27;
+    await provider.send(
+      new anchor.web3.Transaction().add(
+        anchor.web3.SystemProgram.createAccount({
+          fromPubkey: provider.wallet.publicKey,
+          newAccountPubkey: buffer.publicKey,
+          space,
+          lamports: await provider.connection.getMinimumBalanceForRentExemption(
+            space
+          ),
+          programId: anchor.web3.SystemProgram.programId,
+        })
+      ),
+      [buffer]
+    );
+    const [programData] = await anchor.web3.PublicKey.findProgramAddress(
+      [program.programId.toBuffer()],
+      BPF_LOADER_UPGRADEABLE_ID
+    );
+    const readonly = (pubkey) => ({ pubkey, isWritable: false, isSigner: false });
+    const writable = (pubkey) => ({ pubkey, isWritable: true, isSigner: false });
+    const upgradeIx = {
+      programId: program.programId,
+      keys: [
+        writable(ms.multisig),
+        { pubkey: ms.multisigSigner, isWritable: false, isSigner: true },
+        writable(programData),
+        writable(program.programId),
+        writable(buffer.publicKey),
+        writable(provider.wallet.publicKey),
+        readonly(anchor.web3.SYSVAR_RENT_PUBKEY),
+        readonly(anchor.web3.SYSVAR_CLOCK_PUBKEY),
+        readonly(BPF_LOADER_UPGRADEABLE_ID),
+      ],
+      data: program.coder.instruction.encode("upgrade_self", {
+        buffer: buffer.publicKey,
+      }),
+    };
+    const commitIx = (bufferHash) =>
+      authIx(program, ms, "commit_upgrade_buffer", { bufferHash });
+    const run = async (ixs) => {
+      const tx = await propose(program, ms, owners[0], ixs);
+      await approve(program, ms, tx, [owners[1]]);
+      await execute(program, ms, tx, owners[0], ixs);
+    };
+
+    const wrongHash = [...Buffer.alloc(32, 1)];
+    await run([commitIx(wrongHash)]);
+    assert.deepStrictEqual(
+      (await program.account.multisig(ms.multisig)).upgradeBufferHash,
+      wrongHash
+    );
+    assert.ok(
+      await fails(
+        run([upgradeIx]),
+        "The upgrade buffer does not match the committed hash."
+      )
+    );
+
+    // With the right hash, the upgrade gets as far as the authority check.
+    const hash = [
+      ...require("crypto").createHash("sha256").update(Buffer.alloc(27)).digest(),
+    ];
+    await run([commitIx(hash)]);
+    assert.ok(await fails(run([upgradeIx]), NOT_UPGRADE_AUTHORITY));
+  });
+
+  it("Charges the executor a fee per instruction", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const treasury = anchor.web3.Keypair.generate().publicKey;
+    const feeIxs = [
+      authIx(program, ms, "set_proposal_fee", {
+        proposalFeeLamports: new anchor.BN(0),
+        feeTreasury: treasury,
+        refundFeeOnExecute: false,
+      }),
+      authIx(program, ms, "set_execution_fee", {
+        executionComputeFeeLamportsPerIx: new anchor.BN(1000000),
+      }),
+    ];
+    const feeTx = await propose(program, ms, owners[0], feeIxs);
+    await approve(program, ms, feeTx, [owners[1]]);
+    await execute(program, ms, feeTx, owners[0], feeIxs);
+
+    const ixs = [
+      authIx(program, ms, "set_max_instructions", { maxInstructionsGlobal: 5 }),
+      authIx(program, ms, "set_executor_tip", {
+        executorTipLamports: new anchor.BN(0),
+      }),
+    ];
+    const tx = await propose(program, ms, owners[0], ixs);
+    await approve(program, ms, tx, [owners[1]]);
+    const executeAs = (executor) =>
+      program.rpc.executeTransaction({
+        accounts: {
+          multisig: ms.multisig,
+          multisigSigner: ms.multisigSigner,
+          transaction: tx,
+          executor: executor.publicKey,
+        },
+        remainingAccounts: executionAccounts(program, ms, ixs).concat([
+          { pubkey: treasury, isWritable: true, isSigner: false },
+          {
+            pubkey: anchor.web3.SystemProgram.programId,
+            isWritable: false,
+            isSigner: false,
+          },
+        ]),
+        signers: [executor],
+      });
+
+    // owners[1] has no lamports.
+    assert.ok(
+      await fails(
+        executeAs(owners[1]),
+        "The executor cannot afford the execution fee."
+      )
+    );
+    await fund(provider, owners[0].publicKey, 10000000);
+    await executeAs(owners[0]);
+    assert.strictEqual(await provider.connection.getBalance(treasury), 2000000);
+    assert.strictEqual(
+      await provider.connection.getBalance(owners[0].publicKey),
+      8000000
+    );
+  });
+
+  it("Needs the quorum of every approval group", async () => {
+    const owners = [newOwner(), newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_approval_groups", {
+      approvalGroups: [
+        { name: "finance", members: [owners[2].publicKey], quorum: new anchor.BN(1) },
+      ],
+    });
+    const groupTx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, groupTx, [owners[1]]);
+    await execute(program, ms, groupTx, owners[0], [ix]);
+
+    const ix2 = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, owners[0], [ix2]);
+    await approve(program, ms, tx, [owners[1]]);
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, owners[0], [ix2]),
+        "An approval group has not reached its quorum."
+      )
+    );
+    await approve(program, ms, tx, [owners[2]]);
+    await execute(program, ms, tx, owners[0], [ix2]);
+  });
+
+  it("Lets the veto authority cancel an approved transaction", async () => {
+    const owners = [newOwner(), newOwner()];
+    const vetoAuthority = newOwner();
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_veto_authority", {
+      vetoAuthority: vetoAuthority.publicKey,
+    });
+    const vetoTx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, vetoTx, [owners[1]]);
+    await execute(program, ms, vetoTx, owners[0], [ix]);
+
+    const ix2 = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const tx = await propose(program, ms, owners[0], [ix2]);
+    await approve(program, ms, tx, [owners[1]]);
+    const veto = (signer) =>
+      program.rpc.veto({
+        accounts: {
+          multisig: ms.multisig,
+          transaction: tx,
+          vetoAuthority: signer.publicKey,
+        },
+        signers: [signer],
+      });
+    assert.ok(
+      await fails(
+        veto(owners[0]),
+        "The signer is not the multisig's veto authority."
+      )
+    );
+    await veto(vetoAuthority);
+    assert.ok(
+      (await program.account.transaction(tx)).status.cancelled !== undefined
+    );
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, owners[0], [ix2]),
+        "The given transaction has been cancelled."
+      )
+    );
+  });
+
+  it("Needs the higher threshold of a matching tier", async () => {
+    const owners = [newOwner(), newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_threshold_tiers", {
+      thresholdTiers: [
+        {
+          programId: anchor.web3.SystemProgram.programId,
+          dataPrefix: Buffer.from([]),
+          threshold: new anchor.BN(3),
+        },
+      ],
+    });
+    const tierTx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tierTx, [owners[1]]);
+    await execute(program, ms, tierTx, owners[0], [ix]);
+    await fund(provider, ms.multisigSigner, 10000000);
+
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+    const transferIx = anchor.web3.SystemProgram.transfer({
+      fromPubkey: ms.multisigSigner,
+      toPubkey: recipient,
+      lamports: 1000000,
+    });
+    const tx = await propose(program, ms, owners[0], [transferIx]);
+    await approve(program, ms, tx, [owners[1]]);
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, owners[0], [transferIx]),
+        "Not enough owners signed this transaction."
+      )
+    );
+    await approve(program, ms, tx, [owners[2]]);
+    await execute(program, ms, tx, owners[0], [transferIx]);
+    assert.strictEqual(await provider.connection.getBalance(recipient), 1000000);
+  });
+
+  it("Derives the threshold from a percentage of the owners", async () => {
+    const owners = [newOwner(), newOwner(), newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const percentIx = (thresholdPercent) =>
+      authIx(program, ms, "set_threshold_percent", { thresholdPercent });
+
+    const invalidIx = percentIx(101);
+    const invalidTx = await propose(program, ms, owners[0], [invalidIx]);
+    await approve(program, ms, invalidTx, [owners[1]]);
+    assert.ok(
+      await fails(
+        execute(program, ms, invalidTx, owners[0], [invalidIx]),
+        "Threshold must be less than or equal to the number of owners."
+      )
+    );
+
+    // 66% of four owners, rounded up.
+    const ix = percentIx(66);
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix]);
+    let multisigAccount = await program.account.multisig(ms.multisig);
+    assert.strictEqual(multisigAccount.thresholdPercent, 66);
+    assert.ok(multisigAccount.threshold.eq(new anchor.BN(3)));
+
+    // And of three once an owner is removed.
+    const setOwnersIx = authIx(program, ms, "set_owners", {
+      owners: owners.slice(0, 3).map((o) => o.publicKey),
+    });
+    const setOwnersTx = await propose(program, ms, owners[0], [setOwnersIx]);
+    await approve(program, ms, setOwnersTx, [owners[1], owners[2]]);
+    await execute(program, ms, setOwnersTx, owners[0], [setOwnersIx]);
+    multisigAccount = await program.account.multisig(ms.multisig);
+    assert.ok(multisigAccount.threshold.eq(new anchor.BN(2)));
+  });
+
+  it("Reveals only the committed instructions", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const transaction = anchor.web3.Keypair.generate();
+    await program.rpc.createHashedTransaction(
+      instructionsHash([ix]),
+      false,
+      null,
+      0,
+      0,
+      {
+        accounts: {
+          multisig: ms.multisig,
+          transaction: transaction.publicKey,
+          proposer: owners[0].publicKey,
+          rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+        },
+        instructions: [
+          await program.account.transaction.createInstruction(
+            transaction,
+            TX_SIZE
+          ),
+        ],
+        signers: [transaction, owners[0]],
+      }
+    );
+    const tx = transaction.publicKey;
+    assert.deepStrictEqual((await program.account.transaction(tx)).data, []);
+
+    const reveal = (ix) =>
+      program.rpc.revealTransaction([ix.programId], [ix.keys], [ix.data], {
+        accounts: {
+          multisig: ms.multisig,
+          transaction: tx,
+          proposer: owners[0].publicKey,
+        },
+        signers: [owners[0]],
+      });
+    const otherIx = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 6,
+    });
+    assert.ok(
+      await fails(
+        reveal(otherIx),
+        "The instructions don't match the transaction's instructions hash."
+      )
+    );
+    await reveal(ix);
+    assert.ok(
+      (await program.account.transaction(tx)).status.active !== undefined
+    );
+    await approve(program, ms, tx, [owners[1]]);
+    await execute(program, ms, tx, owners[0], [ix]);
+    assert.strictEqual(
+      (await program.account.multisig(ms.multisig)).maxInstructionsGlobal,
+      5
+    );
+  });
+
+  it("Approves several transactions at once", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ixs = [5, 6].map((maxInstructionsGlobal) =>
+      authIx(program, ms, "set_max_instructions", { maxInstructionsGlobal })
+    );
+    const txs = [];
+    for (const ix of ixs) {
+      txs.push(await propose(program, ms, owners[0], [ix]));
+    }
+    const batchApprove = (transactions) =>
+      program.rpc.batchApprove(transactions.length, {
+        accounts: {
+          multisig: ms.multisig,
+          owner: owners[1].publicKey,
+        },
+        remainingAccounts: transactions.map((pubkey) => ({
+          pubkey,
+          isWritable: true,
+          isSigner: false,
+        })),
+        signers: [owners[1]],
+      });
+
+    // A transaction given twice is approved once.
+    assert.ok(
+      await fails(
+        batchApprove([txs[0], txs[0]]),
+        "The owner has already approved this transaction."
+      )
+    );
+    await batchApprove(txs);
+    for (const tx of txs) {
+      assert.strictEqual(
+        (await program.account.transaction(tx)).approvals.length,
+        2
+      );
+    }
+    await execute(program, ms, txs[0], owners[0], [ixs[0]]);
+    await execute(program, ms, txs[1], owners[0], [ixs[1]]);
+    assert.strictEqual(
+      (await program.account.multisig(ms.multisig)).maxInstructionsGlobal,
+      6
+    );
+  });
+
+  it("Executes a recurring transaction after each cooldown", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    await fund(provider, ms.multisigSigner, 10000000);
+    const recipient = anchor.web3.Keypair.generate().publicKey;
+    const ix = anchor.web3.SystemProgram.transfer({
+      fromPubkey: ms.multisigSigner,
+      toPubkey: recipient,
+      lamports: 1000000,
+    });
+    const tx = await propose(program, ms, owners[0], [ix]);
+    await program.rpc.setTransactionRecurrence(2, new anchor.BN(2), {
+      accounts: {
+        multisig: ms.multisig,
+        transaction: tx,
+        proposer: owners[0].publicKey,
+      },
+      signers: [owners[0]],
+    });
+    await approve(program, ms, tx, [owners[1]]);
+
+    await execute(program, ms, tx, owners[0], [ix]);
+    let txAccount = await program.account.transaction(tx);
+    assert.strictEqual(txAccount.executionCount, 1);
+    assert.ok(txAccount.status.approved !== undefined);
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, owners[0], [ix]),
+        "The transaction executed too recently to execute again."
+      )
+    );
+
+    await sleep(3000);
+    await execute(program, ms, tx, owners[0], [ix]);
+    txAccount = await program.account.transaction(tx);
+    assert.strictEqual(txAccount.executionCount, 2);
+    assert.ok(txAccount.status.executed !== undefined);
+    assert.strictEqual(await provider.connection.getBalance(recipient), 2000000);
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, owners[0], [ix]),
+        "The given transaction has already been executed."
+      )
+    );
+  });
+
+  it("Executes only inside the execution window", async () => {
+    const owners = [newOwner(), newOwner()];
+    const ms = await createMultisig(program, owners, 2);
+    const ix = authIx(program, ms, "set_max_instructions", {
+      maxInstructionsGlobal: 5,
+    });
+    const schedule = (tx, notBefore, notAfter) =>
+      program.rpc.setTransactionSchedule(
+        notBefore === null ? null : new anchor.BN(notBefore),
+        notAfter === null ? null : new anchor.BN(notAfter),
+        {
+          accounts: {
+            multisig: ms.multisig,
+            transaction: tx,
+            proposer: owners[0].publicKey,
+          },
+          signers: [owners[0]],
+        }
+      );
+    const now = Math.floor(Date.now() / 1000);
+
+    const tx = await propose(program, ms, owners[0], [ix]);
+    assert.ok(
+      await fails(
+        schedule(tx, now + 60, now + 60),
+        "The execution window must start before it ends."
+      )
+    );
+    await schedule(tx, now + 3, now + 3600);
+    await approve(program, ms, tx, [owners[1]]);
+    assert.ok(
+      await fails(
+        execute(program, ms, tx, owners[0], [ix]),
+        "The transaction can't execute outside its execution window."
+      )
+    );
+    await sleep(5000);
+    await execute(program, ms, tx, owners[0], [ix]);
+
+    // A window that has closed.
+    const closedTx = await propose(program, ms, owners[0], [ix]);
+    await schedule(closedTx, null, now - 60);
+    await approve(program, ms, closedTx, [owners[1]]);
+    assert.ok(
+      await fails(
+        execute(program, ms, closedTx, owners[0], [ix]),
+        "The transaction can't execute outside its execution window."
+      )
+    );
   });
 });
 
 
 
+// The program's MAX_OWNERS.
+const MAX_OWNERS = 10;
+
+// Bytes of a new multisig account with the given number of owners, mirroring
+// Multisig::space. Anything set after creation needs room on top.
+function multisigSpace(owners) {
+  return 247 + 32 * owners;
+}
+
+// Bytes allocated for each transaction account.
+const TX_SIZE = 1000;
+
+function newOwner() {
+  return anchor.web3.Keypair.generate();
+}
+
+function sleep(ms) {
+  return new Promise((resolve) => setTimeout(resolve, ms));
+}
+
+// Whether the promise is rejected, with the given error message, or one of
+// the given messages, if any.
+async function fails(promise, msg) {
+  try {
+    await promise;
+  } catch (err) {
+    return msg === undefined || [].concat(msg).includes(err.msg);
+  }
+  return false;
+}
+
+// What upgrade authority checks fail with, depending on whether the test
+// validator loaded this program as upgradeable.
+const NOT_UPGRADE_AUTHORITY = [
+  "The signer is not the program's upgrade authority.",
+  "The given program data account is invalid.",
+];
+
+// Sends lamports from the provider's wallet.
+async function fund(provider, to, lamports) {
+  const tx = new anchor.web3.Transaction();
+  tx.add(
+    anchor.web3.SystemProgram.transfer({
+      fromPubkey: provider.wallet.publicKey,
+      toPubkey: to,
+      lamports,
+    })
+  );
+  await provider.send(tx);
+}
+
+// Creates a multisig of the given owner keypairs, with room for the policies
+// the tests set on top of Multisig::space.
+async function createMultisig(program, owners, threshold, coldRecoveryKey) {
+  const multisig = anchor.web3.Keypair.generate();
+  const [
+    multisigSigner,
+    nonce,
+  ] = await anchor.web3.PublicKey.findProgramAddress(
+    [multisig.publicKey.toBuffer()],
+    program.programId
+  );
+  await program.rpc.createMultisig(
+    owners.map((o) => o.publicKey),
+    new anchor.BN(threshold),
+    nonce,
+    coldRecoveryKey || null,
+    {
+      accounts: {
+        multisig: multisig.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await program.account.multisig.createInstruction(
+          multisig,
+          multisigSpace(owners.length) + 512
+        ),
+      ],
+      signers: [multisig],
+    }
+  );
+  return { multisig: multisig.publicKey, multisigSigner };
+}
+
+// An instruction calling one of the program's setters, signed by the multisig.
+function authIx(program, ms, name, args) {
+  return {
+    programId: program.programId,
+    keys: [
+      { pubkey: ms.multisig, isWritable: true, isSigner: false },
+      { pubkey: ms.multisigSigner, isWritable: false, isSigner: true },
+    ],
+    data: program.coder.instruction.encode(name, args),
+  };
+}
+
+// Proposes the given instructions, returning the new transaction.
+async function propose(program, ms, proposer, ixs, expiresAt) {
+  const transaction = anchor.web3.Keypair.generate();
+  await program.rpc.createTransaction(
+    ixs.map((ix) => ix.programId),
+    ixs.map((ix) => ix.keys),
+    ixs.map((ix) => ix.data),
+    false,
+    expiresAt || null,
+    0,
+    0,
+    {
+      accounts: {
+        multisig: ms.multisig,
+        transaction: transaction.publicKey,
+        proposer: proposer.publicKey,
+        rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+      },
+      instructions: [
+        await program.account.transaction.createInstruction(
+          transaction,
+          TX_SIZE
+        ),
+      ],
+      signers: [transaction, proposer],
+    }
+  );
+  return transaction.publicKey;
+}
+
+// Proposes the given typed config actions, returning the new transaction.
+async function proposeConfig(program, ms, proposer, actions) {
+  const transaction = anchor.web3.Keypair.generate();
+  await program.rpc.proposeConfigChange(actions, null, {
+    accounts: {
+      multisig: ms.multisig,
+      transaction: transaction.publicKey,
+      proposer: proposer.publicKey,
+      rent: anchor.web3.SYSVAR_RENT_PUBKEY,
+    },
+    instructions: [
+      await program.account.transaction.createInstruction(transaction, TX_SIZE),
+    ],
+    signers: [transaction, proposer],
+  });
+  return transaction.publicKey;
+}
+
+async function approve(program, ms, transaction, owners) {
+  for (const owner of owners) {
+    await program.rpc.approve({
+      accounts: {
+        multisig: ms.multisig,
+        transaction,
+        owner: owner.publicKey,
+      },
+      signers: [owner],
+    });
+  }
+}
+
+// The remaining accounts executing the given instructions needs: their
+// accounts, with the multisig's signatures left to the program, and their
+// programs.
+function executionAccounts(program, ms, ixs) {
+  const metas = [];
+  const add = (pubkey, isWritable) => {
+    const meta = metas.find((m) => m.pubkey.equals(pubkey));
+    if (meta) {
+      meta.isWritable = meta.isWritable || isWritable;
+    } else {
+      metas.push({ pubkey, isWritable, isSigner: false });
+    }
+  };
+  for (const ix of ixs) {
+    ix.keys.forEach((k) => add(k.pubkey, k.isWritable));
+    add(ix.programId, false);
+  }
+  add(program.programId, false);
+  return metas;
+}
+
+async function execute(program, ms, transaction, executor, ixs) {
+  await program.rpc.executeTransaction({
+    accounts: {
+      multisig: ms.multisig,
+      multisigSigner: ms.multisigSigner,
+      transaction,
+      executor: executor.publicKey,
+    },
+    remainingAccounts: executionAccounts(program, ms, ixs),
+    signers: [executor],
+  });
+}
+
+// The hash `create_hashed_transaction` commits to, of the Borsh encoded
+// `reveal_transaction` arguments.
+function instructionsHash(ixs) {
+  const vec = (items, encode) => {
+    const len = Buffer.alloc(4);
+    len.writeUInt32LE(items.length);
+    return Buffer.concat([len, ...items.map(encode)]);
+  };
+  const pid = vec(ixs, (ix) => ix.programId.toBuffer());
+  const accs = vec(ixs, (ix) =>
+    vec(ix.keys, (k) =>
+      Buffer.concat([
+        k.pubkey.toBuffer(),
+        Buffer.from([k.isSigner ? 1 : 0, k.isWritable ? 1 : 0]),
+      ])
+    )
+  );
+  const data = vec(ixs, (ix) => vec([...ix.data], (b) => Buffer.from([b])));
+  return [
+    ...require("crypto")
+      .createHash("sha256")
+      .update(Buffer.concat([pid, accs, data]))
+      .digest(),
+  ];
+}
+
+const BPF_LOADER_UPGRADEABLE_ID = new anchor.web3.PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+const TOKEN_PROGRAM_ID = new anchor.web3.PublicKey(
+  "TokenkegQfeZyiNwAJbNbGKPFXCWuBvf9Ss623VQ5DA"
+);
+
+// Creates a mint with no decimals and the given authorities.
+async function createMint(provider, mintAuthority, freezeAuthority) {
+  const mint = anchor.web3.Keypair.generate();
+  const tx = new anchor.web3.Transaction();
+  tx.add(
+    anchor.web3.SystemProgram.createAccount({
+      fromPubkey: provider.wallet.publicKey,
+      newAccountPubkey: mint.publicKey,
+      space: 82,
+      lamports: await provider.connection.getMinimumBalanceForRentExemption(82),
+      programId: TOKEN_PROGRAM_ID,
+    }),
+    {
+      programId: TOKEN_PROGRAM_ID,
+      keys: [
+        { pubkey: mint.publicKey, isWritable: true, isSigner: false },
+        {
+          pubkey: anchor.web3.SYSVAR_RENT_PUBKEY,
+          isWritable: false,
+          isSigner: false,
+        },
+      ],
+      // InitializeMint.
+      data: Buffer.concat([
+        Buffer.from([0, 0]),
+        mintAuthority.toBuffer(),
+        Buffer.from([1]),
+        freezeAuthority.toBuffer(),
+      ]),
+    }
+  );
+  await provider.send(tx, [mint]);
+  return mint.publicKey;
+}
+
+// Creates a token account of the given mint and owner.
+async function createTokenAccount(provider, mint, owner) {
+  const account = anchor.web3.Keypair.generate();
+  const tx = new anchor.web3.Transaction();
+  tx.add(
+    anchor.web3.SystemProgram.createAccount({
+      fromPubkey: provider.wallet.publicKey,
+      newAccountPubkey: account.publicKey,
+      space: 165,
+      lamports: await provider.connection.getMinimumBalanceForRentExemption(
+        165
+      ),
+      programId: TOKEN_PROGRAM_ID,
+    }),
+    {
+      programId: TOKEN_PROGRAM_ID,
+      keys: [
+        { pubkey: account.publicKey, isWritable: true, isSigner: false },
+        { pubkey: mint, isWritable: false, isSigner: false },
+        { pubkey: owner, isWritable: false, isSigner: false },
+        {
+          pubkey: anchor.web3.SYSVAR_RENT_PUBKEY,
+          isWritable: false,
+          isSigner: false,
+        },
+      ],
+      // InitializeAccount.
+      data: Buffer.from([1]),
+    }
+  );
+  await provider.send(tx, [account]);
+  return account.publicKey;
+}
+
 async function transfer(provider, from, to, authority) {
 
   const instructions = [anchor.web3.SystemProgram.transfer({